@@ -0,0 +1,170 @@
+use std::io::{self, Read, Write};
+
+use event::{DecodeError, Reader};
+use fragment::{Fragment, LoadError, OwnedFragment};
+
+/// Several named Fragments stored together, e.g. in one file.
+///
+/// Each Fragment is kept in the format of `Fragment::write_to`, so it is
+/// only decoded when asked for by `get`.
+///
+/// # Example
+/// ```
+/// use pender::archive::Archive;
+/// use pender::fragment::Fragment;
+///
+/// let mut frag = Fragment::new();
+/// frag.append(b"potato").unwrap();
+///
+/// let mut archive = Archive::new();
+/// archive.add("vegetables", &frag).unwrap();
+/// let mut buf = Vec::new();
+/// archive.write_to(&mut buf).unwrap();
+///
+/// let archive = Archive::read_from(&mut &buf[..]).unwrap();
+/// let loaded = archive.get("vegetables").unwrap().unwrap();
+/// assert_eq!(loaded.as_fragment(), frag);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Archive {
+    entries: Vec<(String, Vec<u8>)>,
+}
+
+impl Archive {
+    pub fn new() -> Archive {
+        Default::default()
+    }
+
+    /// Store `frag` under `name`, replacing any Fragment already stored
+    /// under it. Fails, storing nothing, if `Fragment::write_to` refuses
+    /// `frag` (because it has a domain or key).
+    pub fn add(&mut self, name: &str, frag: &Fragment) -> io::Result<()> {
+        let mut bytes = Vec::new();
+        frag.write_to(&mut bytes)?;
+        match self.entries.iter_mut().find(|entry| entry.0 == name) {
+            Some(entry) => entry.1 = bytes,
+            None => self.entries.push((name.to_string(), bytes)),
+        }
+        Ok(())
+    }
+
+    /// The names of the stored Fragments, in the order they were added.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|entry| &entry.0[..])
+    }
+
+    /// Decode the Fragment stored under `name`, or None if there is none.
+    pub fn get(&self, name: &str) -> Option<Result<OwnedFragment, LoadError>> {
+        self.entries.iter()
+            .find(|entry| entry.0 == name)
+            .map(|entry| Fragment::read_from(&mut &entry.1[..]))
+    }
+
+    /// Write the Archive to `w`.
+    ///
+    /// The format is the magic bytes `PNDA`, a version byte, and the number
+    /// of Fragments as a big-endian u64. Each Fragment follows as its
+    /// name's length in bytes as a big-endian u64, the UTF-8 name, the
+    /// length of the Fragment as a big-endian u64, and the Fragment as
+    /// written by `Fragment::write_to`.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        w.write_all(&[VERSION])?;
+        w.write_all(&(self.entries.len() as u64).to_be_bytes())?;
+        for (name, bytes) in &self.entries {
+            w.write_all(&(name.len() as u64).to_be_bytes())?;
+            w.write_all(name.as_bytes())?;
+            w.write_all(&(bytes.len() as u64).to_be_bytes())?;
+            w.write_all(bytes)?;
+        }
+        Ok(())
+    }
+
+    /// Read an Archive written by `write_to`. Fails if any Fragment is cut
+    /// short, but doesn't decode the Fragments themselves; `get` does that.
+    pub fn read_from<R: Read>(r: &mut R) -> Result<Archive, LoadError> {
+        let mut buf = Vec::new();
+        r.read_to_end(&mut buf)?;
+
+        if buf.len() < MAGIC.len() + 1 {
+            return Err(LoadError::Decode(DecodeError::Truncated));
+        }
+        if &buf[..4] != MAGIC {
+            return Err(LoadError::BadMagic);
+        }
+        if buf[4] != VERSION {
+            return Err(LoadError::UnsupportedVersion(buf[4]));
+        }
+
+        let mut reader = Reader::new(&buf[5..]);
+        let count = reader.take_len(16)?;
+        let mut entries = Vec::with_capacity(count);
+        for _ in 0..count {
+            let len = reader.take_len(1)?;
+            let name = String::from_utf8(reader.take(len)?.to_vec())
+                .map_err(|_| LoadError::BadName)?;
+            let len = reader.take_len(1)?;
+            entries.push((name, reader.take(len)?.to_vec()));
+        }
+        if reader.remaining() != 0 {
+            return Err(LoadError::Decode(DecodeError::TrailingBytes(reader.remaining())));
+        }
+        Ok(Archive { entries: entries })
+    }
+}
+
+const MAGIC: &[u8] = b"PNDA";
+const VERSION: u8 = 1;
+
+#[cfg(test)]
+mod tests {
+    use event::DecodeError;
+    use fragment::{Fragment, LoadError};
+    use super::Archive;
+
+    fn archive() -> (Archive, Fragment<'static>, Fragment<'static>) {
+        let mut fruit = Fragment::new();
+        fruit.append_all(&[b"apple", b"banana"]).unwrap();
+        let mut vegetables = Fragment::new();
+        vegetables.append(b"potato").unwrap();
+
+        let mut archive = Archive::new();
+        archive.add("fruit", &fruit).unwrap();
+        archive.add("vegetables", &vegetables).unwrap();
+        (archive, fruit, vegetables)
+    }
+
+    #[test]
+    fn archive_round_trips_named_fragments() {
+        let (archive, fruit, vegetables) = archive();
+        let mut buf = Vec::new();
+        archive.write_to(&mut buf).unwrap();
+
+        let loaded = Archive::read_from(&mut &buf[..]).unwrap();
+        assert_eq!(loaded.names().collect::<Vec<_>>(), ["fruit", "vegetables"]);
+        assert_eq!(loaded.get("fruit").unwrap().unwrap().as_fragment(), fruit);
+        assert_eq!(loaded.get("vegetables").unwrap().unwrap().as_fragment(), vegetables);
+        assert!(loaded.get("minerals").is_none());
+    }
+
+    #[test]
+    fn add_replaces_fragment_of_same_name() {
+        let (mut archive, fruit, _) = archive();
+        archive.add("vegetables", &fruit).unwrap();
+        assert_eq!(archive.names().count(), 2);
+        assert_eq!(archive.get("vegetables").unwrap().unwrap().as_fragment(), fruit);
+    }
+
+    #[test]
+    fn truncated_archive_is_rejected() {
+        let (archive, _, _) = archive();
+        let mut buf = Vec::new();
+        archive.write_to(&mut buf).unwrap();
+        for len in 0..buf.len() {
+            match Archive::read_from(&mut &buf[..len]) {
+                Err(LoadError::Decode(DecodeError::Truncated)) => (),
+                other => panic!("unexpected {:?} at length {}", other, len),
+            }
+        }
+    }
+}
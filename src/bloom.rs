@@ -0,0 +1,180 @@
+#[cfg(feature = "serde")]
+use serde::{de, Deserialize, Deserializer, Serialize};
+
+use hash::EventHash;
+#[cfg(not(feature = "std"))]
+use prelude::*;
+
+/// A Bloom filter of Event hashes: a compact, lossy set that can say an
+/// Event is definitely absent, or probably present.
+///
+/// A node can send its filter (see `Fragment::bloom`) so that a peer skips
+/// sending Events it probably already has. There are no false negatives;
+/// the rate of false positives depends on the filter's size and the number
+/// of hashes inserted (see `false_positive_rate`).
+///
+/// # Example
+/// ```
+/// use pender::bloom::BloomFilter;
+/// use pender::hash::Blake2;
+///
+/// let mut bloom = BloomFilter::new(1024, 4);
+/// bloom.insert(&Blake2::new(b"potato"));
+/// assert!(bloom.might_contain(&Blake2::new(b"potato")));
+/// ```
+///
+/// With the `serde` feature, deserializing checks that the filter is one
+/// `new` could have made, so a filter sent by a peer can't make the
+/// methods panic.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct BloomFilter {
+    words: Vec<u64>,
+    bits: usize,
+    hashes: usize,
+}
+
+impl BloomFilter {
+    /// An empty filter of `bits` bits (at least 1), setting `hashes` bits
+    /// (at least 1) per inserted hash.
+    pub fn new(bits: usize, hashes: usize) -> BloomFilter {
+        let bits = bits.max(1);
+        BloomFilter {
+            words: vec![0; bits.div_ceil(64)],
+            bits: bits,
+            hashes: hashes.max(1),
+        }
+    }
+
+    pub fn insert<H: EventHash>(&mut self, hash: &H) {
+        for bit in self.positions(hash) {
+            self.words[bit / 64] |= 1 << (bit % 64);
+        }
+    }
+
+    /// False if `hash` was certainly never inserted; true if it probably
+    /// was.
+    pub fn might_contain<H: EventHash>(&self, hash: &H) -> bool {
+        self.positions(hash).all(|bit| self.words[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    /// The expected chance that `might_contain` is true for a hash that
+    /// wasn't inserted, once `items` distinct hashes have been. Needs the
+    /// `std` feature, for floating-point `exp` and `powf`.
+    #[cfg(feature = "std")]
+    pub fn false_positive_rate(&self, items: usize) -> f64 {
+        let k = self.hashes as f64;
+        let fill = 1.0 - (-k * items as f64 / self.bits as f64).exp();
+        fill.powf(k)
+    }
+
+    /// The bits `hash` sets. The first sixteen bytes of the hash give two
+    /// numbers, `a` and `b`, and the `i`th bit is `a + i * b` (modulo the
+    /// size), which is as good as `hashes` independent hash functions.
+    fn positions<H: EventHash>(&self, hash: &H) -> impl Iterator<Item = usize> {
+        let bytes = hash.as_ref();
+        let word = |at: usize| {
+            let mut word = [0u8; 8];
+            for (i, byte) in word.iter_mut().enumerate() {
+                *byte = bytes[(at + i) % bytes.len()];
+            }
+            u64::from_le_bytes(word)
+        };
+        let (a, b) = (word(0), word(8) | 1);
+        let bits = self.bits as u64;
+        (0..self.hashes as u64).map(move |i| (a.wrapping_add(i.wrapping_mul(b)) % bits) as usize)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for BloomFilter {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<BloomFilter, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(rename = "BloomFilter")]
+        struct Unchecked {
+            words: Vec<u64>,
+            bits: usize,
+            hashes: usize,
+        }
+
+        let filter = Unchecked::deserialize(deserializer)?;
+        if filter.bits == 0 {
+            return Err(de::Error::custom("BloomFilter has no bits"));
+        }
+        if filter.hashes == 0 {
+            return Err(de::Error::custom("BloomFilter sets no bits per hash"));
+        }
+        if filter.words.len() != filter.bits.div_ceil(64) {
+            return Err(de::Error::custom(format_args!(
+                "BloomFilter of {} bits can't have {} words", filter.bits, filter.words.len())));
+        }
+        Ok(BloomFilter { words: filter.words, bits: filter.bits, hashes: filter.hashes })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hash::Blake2;
+    use super::BloomFilter;
+
+    fn hashes(prefix: &str, n: usize) -> Vec<Blake2> {
+        (0..n).map(|i| Blake2::new(format!("{}{}", prefix, i).as_bytes())).collect()
+    }
+
+    #[test]
+    fn inserted_hashes_are_always_found() {
+        let mut bloom = BloomFilter::new(2048, 5);
+        let inserted = hashes("in", 300);
+        for hash in &inserted {
+            bloom.insert(hash);
+        }
+        assert!(inserted.iter().all(|hash| bloom.might_contain(hash)));
+    }
+
+    #[test]
+    fn empty_filter_contains_nothing() {
+        let bloom = BloomFilter::new(64, 3);
+        assert!(!bloom.might_contain(&Blake2::new(b"foo")));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn false_positive_rate_is_near_expected() {
+        let mut bloom = BloomFilter::new(10_000, 7);
+        for hash in &hashes("in", 1000) {
+            bloom.insert(hash);
+        }
+        let expected = bloom.false_positive_rate(1000);
+        assert!(expected < 0.01);
+
+        let trials = 10_000;
+        let false_positives = hashes("out", trials).iter()
+            .filter(|hash| bloom.might_contain(*hash))
+            .count();
+        let rate = false_positives as f64 / trials as f64;
+        assert!(rate < 2.0 * expected, "rate {} vs expected {}", rate, expected);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn filter_round_trips_through_json() {
+        let mut bloom = BloomFilter::new(100, 3);
+        bloom.insert(&Blake2::new(b"foo"));
+        let json = ::serde_json::to_string(&bloom).unwrap();
+        assert_eq!(::serde_json::from_str::<BloomFilter>(&json).unwrap(), bloom);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn malformed_filters_fail_to_deserialize() {
+        for json in &[r#"{"words":[0],"bits":0,"hashes":3}"#,
+                      r#"{"words":[],"bits":0,"hashes":3}"#,
+                      r#"{"words":[0],"bits":64,"hashes":0}"#,
+                      r#"{"words":[0],"bits":65,"hashes":3}"#,
+                      r#"{"words":[0,0],"bits":64,"hashes":3}"#] {
+            assert!(::serde_json::from_str::<BloomFilter>(json).is_err(), "accepted {}", json);
+        }
+        let valid = r#"{"words":[0,0],"bits":65,"hashes":3}"#;
+        assert!(::serde_json::from_str::<BloomFilter>(valid).is_ok());
+    }
+}
@@ -0,0 +1,36 @@
+//! The maps and sets a Fragment is built from.
+//!
+//! With the `std` feature these are `HashMap` and `HashSet`; without it they
+//! are `alloc`'s `BTreeMap` and `BTreeSet`, which only need `EventHash`'s
+//! `Ord`. Either way a Fragment's Events iterate in no particular order
+//! that callers should rely on.
+
+#[cfg(feature = "std")]
+pub use std::collections::{hash_map as map, HashMap as Map, HashSet as Set, VecDeque};
+
+#[cfg(not(feature = "std"))]
+pub use alloc::collections::{btree_map as map, BTreeMap as Map, BTreeSet as Set, VecDeque};
+
+/// An empty `Map` with room for `n` entries, where the map type can reserve.
+#[cfg(feature = "std")]
+pub fn map_with_capacity<K, V>(n: usize) -> Map<K, V> {
+    Map::with_capacity(n)
+}
+
+/// An empty `Map` with room for `n` entries, where the map type can reserve.
+#[cfg(not(feature = "std"))]
+pub fn map_with_capacity<K: Ord, V>(_n: usize) -> Map<K, V> {
+    Map::new()
+}
+
+/// An empty `Set` with room for `n` entries, where the set type can reserve.
+#[cfg(feature = "std")]
+pub fn set_with_capacity<T>(n: usize) -> Set<T> {
+    Set::with_capacity(n)
+}
+
+/// An empty `Set` with room for `n` entries, where the set type can reserve.
+#[cfg(not(feature = "std"))]
+pub fn set_with_capacity<T: Ord>(_n: usize) -> Set<T> {
+    Set::new()
+}
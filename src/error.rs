@@ -0,0 +1,388 @@
+//! One error type for everything in Pender that can fail, so that code
+//! calling several fallible methods can use `?` on all of them.
+//!
+//! Each method still returns its own, more specific error (e.g.
+//! `AppendError`), which converts into a `PenderError` with `From`. Every
+//! error type is `Display`, and with the `std` feature an `Error`.
+
+use core::fmt::{self, Display, Formatter};
+#[cfg(feature = "std")]
+use std::error::Error;
+#[cfg(feature = "std")]
+use std::io;
+
+use event::DecodeError;
+#[cfg(feature = "std")]
+use fragment::LoadError;
+use fragment::{AppendError, GraftError, IntegrityError, MergeError, ResolveError, SliceError,
+               TopoSortError};
+use hash::{Blake2, EventHash, HashError, HashParseError};
+
+/// Any of Pender's errors, for Fragments of Events hashed with `H`.
+///
+/// ```
+/// use pender::error::PenderError;
+/// use pender::fragment::Fragment;
+///
+/// fn two_facts() -> Result<Fragment<'static>, PenderError> {
+///     let mut frag = Fragment::new();
+///     frag.try_append(b"one")?;
+///     frag.try_append(b"two")?;
+///     frag.verify()?;
+///     Ok(frag)
+/// }
+///
+/// assert_eq!(two_facts().unwrap().len(), 2);
+/// ```
+#[derive(Debug)]
+pub enum PenderError<H: EventHash = Blake2> {
+    HashParse(HashParseError),
+    Hash(HashError),
+    Decode(DecodeError),
+    #[cfg(feature = "std")]
+    Load(LoadError),
+    #[cfg(feature = "std")]
+    Io(io::Error),
+    Append(AppendError<H>),
+    Integrity(IntegrityError<H>),
+    Resolve(ResolveError<H>),
+    Graft(GraftError<H>),
+    Merge(MergeError),
+    Slice(SliceError<H>),
+    TopoSort(TopoSortError<H>),
+}
+
+impl<H: EventHash> Display for PenderError<H> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            PenderError::HashParse(ref err) => write!(f, "bad hex hash: {}", err),
+            PenderError::Hash(ref err) => write!(f, "bad hash: {}", err),
+            PenderError::Decode(ref err) => write!(f, "can't decode Event: {}", err),
+            #[cfg(feature = "std")]
+            PenderError::Load(ref err) => write!(f, "can't load Fragment: {}", err),
+            #[cfg(feature = "std")]
+            PenderError::Io(ref err) => write!(f, "I/O error: {}", err),
+            PenderError::Append(ref err) => write!(f, "can't append Event: {}", err),
+            PenderError::Integrity(ref err) => write!(f, "corrupt Fragment: {}", err),
+            PenderError::Resolve(ref err) => write!(f, "can't resolve hash prefix: {}", err),
+            PenderError::Graft(ref err) => write!(f, "can't graft Events: {}", err),
+            PenderError::Merge(ref err) => write!(f, "can't merge Fragments: {}", err),
+            PenderError::Slice(ref err) => write!(f, "can't slice Events: {}", err),
+            PenderError::TopoSort(ref err) => write!(f, "can't sort Events: {}", err),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<H: EventHash + 'static> Error for PenderError<H> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            PenderError::HashParse(ref err) => Some(err),
+            PenderError::Hash(ref err) => Some(err),
+            PenderError::Decode(ref err) => Some(err),
+            PenderError::Load(ref err) => Some(err),
+            PenderError::Io(ref err) => Some(err),
+            PenderError::Append(ref err) => Some(err),
+            PenderError::Integrity(ref err) => Some(err),
+            PenderError::Resolve(ref err) => Some(err),
+            PenderError::Graft(ref err) => Some(err),
+            PenderError::Merge(ref err) => Some(err),
+            PenderError::Slice(ref err) => Some(err),
+            PenderError::TopoSort(ref err) => Some(err),
+        }
+    }
+}
+
+impl<H: EventHash> From<HashParseError> for PenderError<H> {
+    fn from(err: HashParseError) -> PenderError<H> {
+        PenderError::HashParse(err)
+    }
+}
+
+impl<H: EventHash> From<HashError> for PenderError<H> {
+    fn from(err: HashError) -> PenderError<H> {
+        PenderError::Hash(err)
+    }
+}
+
+impl<H: EventHash> From<DecodeError> for PenderError<H> {
+    fn from(err: DecodeError) -> PenderError<H> {
+        PenderError::Decode(err)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<H: EventHash> From<LoadError> for PenderError<H> {
+    fn from(err: LoadError) -> PenderError<H> {
+        PenderError::Load(err)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<H: EventHash> From<io::Error> for PenderError<H> {
+    fn from(err: io::Error) -> PenderError<H> {
+        PenderError::Io(err)
+    }
+}
+
+impl<H: EventHash> From<AppendError<H>> for PenderError<H> {
+    fn from(err: AppendError<H>) -> PenderError<H> {
+        PenderError::Append(err)
+    }
+}
+
+impl<H: EventHash> From<IntegrityError<H>> for PenderError<H> {
+    fn from(err: IntegrityError<H>) -> PenderError<H> {
+        PenderError::Integrity(err)
+    }
+}
+
+impl<H: EventHash> From<ResolveError<H>> for PenderError<H> {
+    fn from(err: ResolveError<H>) -> PenderError<H> {
+        PenderError::Resolve(err)
+    }
+}
+
+impl<H: EventHash> From<GraftError<H>> for PenderError<H> {
+    fn from(err: GraftError<H>) -> PenderError<H> {
+        PenderError::Graft(err)
+    }
+}
+
+impl<H: EventHash> From<MergeError> for PenderError<H> {
+    fn from(err: MergeError) -> PenderError<H> {
+        PenderError::Merge(err)
+    }
+}
+
+impl<H: EventHash> From<SliceError<H>> for PenderError<H> {
+    fn from(err: SliceError<H>) -> PenderError<H> {
+        PenderError::Slice(err)
+    }
+}
+
+impl<H: EventHash> From<TopoSortError<H>> for PenderError<H> {
+    fn from(err: TopoSortError<H>) -> PenderError<H> {
+        PenderError::TopoSort(err)
+    }
+}
+
+impl Display for HashParseError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            HashParseError::BadLength(len) => write!(f, "{} hex digits is the wrong length", len),
+            HashParseError::InvalidChar(c) => write!(f, "{:?} is not a hex digit", c),
+        }
+    }
+}
+
+impl Display for HashError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            HashError::BadLength(len) => write!(f, "{} bytes is the wrong length", len),
+        }
+    }
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::Truncated => f.write_str("input ended early"),
+            DecodeError::BadTag(tag) => write!(f, "unknown Event tag {}", tag),
+            DecodeError::TrailingBytes(n) => write!(f, "{} bytes left over", n),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Display for LoadError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            LoadError::Io(ref err) => Display::fmt(err, f),
+            LoadError::BadMagic => f.write_str("not a Pender file"),
+            LoadError::UnsupportedVersion(version) =>
+                write!(f, "unsupported format version {}", version),
+            LoadError::Decode(ref err) => Display::fmt(err, f),
+            LoadError::MissingHead(head) => write!(f, "head {} is not stored", head.to_hex()),
+            LoadError::BadReference(index) => write!(f, "bad back-reference {}", index),
+            LoadError::BadName => f.write_str("Fragment name is not UTF-8"),
+        }
+    }
+}
+
+impl<H: EventHash> Display for AppendError<H> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            AppendError::MissingParent(parent) =>
+                write!(f, "parent {} is not stored", parent.to_hex()),
+            AppendError::FactTooLarge { size, limit } =>
+                write!(f, "{}-byte Fact is over the limit of {} bytes", size, limit),
+            AppendError::Sealed => f.write_str("Fragment is sealed"),
+        }
+    }
+}
+
+impl<H: EventHash> Display for IntegrityError<H> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            IntegrityError::HashMismatch { stored, computed } =>
+                write!(f, "Event stored as {} hashes to {}", stored.to_hex(), computed.to_hex()),
+            IntegrityError::MissingParent(parent) =>
+                write!(f, "parent {} is not stored", parent.to_hex()),
+        }
+    }
+}
+
+impl<H: EventHash> Display for ResolveError<H> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            ResolveError::NotFound => f.write_str("no Event matches"),
+            ResolveError::Ambiguous(ref hashes) => write!(f, "{} Events match", hashes.len()),
+        }
+    }
+}
+
+impl<H: EventHash> Display for GraftError<H> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            GraftError::NotFound(hash) => write!(f, "{} is not in the source", hash.to_hex()),
+            GraftError::Sealed => f.write_str("Fragment is sealed"),
+        }
+    }
+}
+
+impl Display for MergeError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            MergeError::DomainMismatch => f.write_str("the Fragments have different domains"),
+            MergeError::KeyMismatch => f.write_str("the Fragments have different keys"),
+            MergeError::Sealed => f.write_str("Fragment is sealed"),
+        }
+    }
+}
+
+impl<H: EventHash> Display for SliceError<H> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            SliceError::NotFound(hash) => write!(f, "{} is not stored", hash.to_hex()),
+            SliceError::NotAncestor => f.write_str("`from` is not an ancestor of `to`"),
+        }
+    }
+}
+
+impl<H: EventHash> Display for TopoSortError<H> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            TopoSortError::Cycle(ref hashes) => write!(f, "{} Events form a cycle", hashes.len()),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for HashParseError { }
+
+#[cfg(feature = "std")]
+impl Error for HashError { }
+
+#[cfg(feature = "std")]
+impl Error for DecodeError { }
+
+#[cfg(feature = "std")]
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            LoadError::Io(ref err) => Some(err),
+            LoadError::Decode(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<H: EventHash> Error for AppendError<H> { }
+
+#[cfg(feature = "std")]
+impl<H: EventHash> Error for IntegrityError<H> { }
+
+#[cfg(feature = "std")]
+impl<H: EventHash> Error for ResolveError<H> { }
+
+#[cfg(feature = "std")]
+impl<H: EventHash> Error for GraftError<H> { }
+
+#[cfg(feature = "std")]
+impl Error for MergeError { }
+
+#[cfg(feature = "std")]
+impl<H: EventHash> Error for SliceError<H> { }
+
+#[cfg(feature = "std")]
+impl<H: EventHash> Error for TopoSortError<H> { }
+
+#[cfg(test)]
+mod tests {
+    use event::{DecodeError, Event};
+    use fragment::{AppendError, Fragment, GraftError, IntegrityError, MergeError, ResolveError,
+                   SliceError, TopoSortError};
+    #[cfg(feature = "std")]
+    use fragment::LoadError;
+    use hash::{Blake2, HashError, HashParseError};
+    use super::PenderError;
+
+    fn message<E: Into<PenderError>>(err: E) -> String {
+        err.into().to_string()
+    }
+
+    #[test]
+    fn every_error_converts_and_displays() {
+        let hash = Event::new(b"one", None).hash();
+        let hex = hash.to_hex();
+        assert_eq!(message(HashParseError::InvalidChar('x')),
+                   "bad hex hash: 'x' is not a hex digit");
+        assert_eq!(message(HashError::BadLength(3)), "bad hash: 3 bytes is the wrong length");
+        assert_eq!(message(DecodeError::BadTag(7)), "can't decode Event: unknown Event tag 7");
+        assert_eq!(message(AppendError::MissingParent(hash)),
+                   format!("can't append Event: parent {} is not stored", hex));
+        assert_eq!(message(AppendError::<Blake2>::Sealed),
+                   "can't append Event: Fragment is sealed");
+        assert_eq!(message(IntegrityError::MissingParent(hash)),
+                   format!("corrupt Fragment: parent {} is not stored", hex));
+        assert_eq!(message(ResolveError::Ambiguous(vec![hash, hash])),
+                   "can't resolve hash prefix: 2 Events match");
+        assert_eq!(message(GraftError::NotFound(hash)),
+                   format!("can't graft Events: {} is not in the source", hex));
+        assert_eq!(message(MergeError::KeyMismatch),
+                   "can't merge Fragments: the Fragments have different keys");
+        assert_eq!(message(SliceError::<Blake2>::NotAncestor),
+                   "can't slice Events: `from` is not an ancestor of `to`");
+        assert_eq!(message(TopoSortError::Cycle(vec![hash])),
+                   "can't sort Events: 1 Events form a cycle");
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn io_errors_convert_and_display() {
+        use std::error::Error;
+        use std::io;
+
+        assert_eq!(message(LoadError::BadMagic), "can't load Fragment: not a Pender file");
+        assert_eq!(message(LoadError::Decode(DecodeError::Truncated)),
+                   "can't load Fragment: input ended early");
+        let err: PenderError = io::Error::other("disk on fire").into();
+        assert_eq!(err.to_string(), "I/O error: disk on fire");
+        assert!(err.source().is_some());
+    }
+
+    #[test]
+    fn question_mark_converts_specific_errors() {
+        #[allow(clippy::result_large_err)]
+        fn append_then_resolve(frag: &mut Fragment) -> Result<Blake2, PenderError> {
+            frag.try_append(b"one")?;
+            Ok(frag.resolve_prefix("zz")?)
+        }
+        match append_then_resolve(&mut Fragment::new()) {
+            Err(PenderError::Resolve(ResolveError::NotFound)) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
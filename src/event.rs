@@ -1,4 +1,15 @@
-use hash::Blake2;
+use core::cell::OnceCell;
+use core::fmt;
+use core::ops::Range;
+use core::slice;
+use core::str;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use hash::{Blake2, Blake2b, Blake2s, EventHash};
+#[cfg(not(feature = "std"))]
+use prelude::*;
 
 /// A wrapper that imposes partial ordering on chunks of binary data (here
 /// called "Facts").
@@ -15,10 +26,35 @@ use hash::Blake2;
 /// assert_eq!(dad.parent(), Some(grandma.hash()));
 /// assert_eq!(child.parent(), Some(dad.hash()));
 /// ```
-#[derive(Copy, Clone, Debug, PartialEq)]
-pub enum Event<'a> {
-    Root { fact: &'a [u8] },
-    Node { fact: &'a [u8], parent_hash: Blake2 },
+///
+/// With the `serde` feature, facts are serialized as strings in
+/// human-readable formats when they are valid UTF-8. Deserializing an Event
+/// borrows its Fact from the input, which not every input allows (e.g. JSON
+/// strings containing escapes, or any Merge event); deserialize an
+/// `OwnedEvent` in that case.
+///
+/// Events are identified by `Blake2` hashes unless another `EventHash` is
+/// given as `H`.
+#[derive(Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Event<'a, H: EventHash = Blake2> {
+    Root {
+        #[cfg_attr(feature = "serde", serde(borrow, serialize_with = "serde_fact::serialize"))]
+        fact: &'a [u8],
+    },
+    Node {
+        #[cfg_attr(feature = "serde", serde(borrow, serialize_with = "serde_fact::serialize"))]
+        fact: &'a [u8],
+        parent_hash: H,
+    },
+    /// An Event reconciling several branches. The order of `parents` does
+    /// not affect the hash.
+    #[cfg_attr(feature = "serde", serde(skip_deserializing))]
+    Merge {
+        #[cfg_attr(feature = "serde", serde(serialize_with = "serde_fact::serialize"))]
+        fact: &'a [u8],
+        parents: &'a [H],
+    },
 }
 
 impl<'a> Event<'a> {
@@ -26,6 +62,12 @@ impl<'a> Event<'a> {
     ///
     /// Pass `Some(Event)` to make a Node event, or `None` for a Root.
     ///
+    /// Empty Facts are allowed. An empty Root hashes just the empty
+    /// domain's length, its tag and the length 0, and an empty Node still
+    /// differs from its parent because the parent's hash is part of what it
+    /// hashes. See `hash` for the whole preimage, which differs from
+    /// earlier versions' even for Events with no domain.
+    ///
     /// ```
     /// use pender::event::Event;
     ///
@@ -35,6 +77,43 @@ impl<'a> Event<'a> {
     /// assert!(!node.is_root());
     /// ```
     pub fn new(fact: &'a [u8], parent: Option<Event>) -> Event<'a> {
+        Event::with_parent(fact, parent)
+    }
+
+    /// Like `new`, with a UTF-8 Fact.
+    ///
+    /// ```
+    /// use pender::event::Event;
+    ///
+    /// assert_eq!(Event::new_str("potato", None), Event::new(b"potato", None));
+    /// ```
+    pub fn new_str(fact: &'a str, parent: Option<Event>) -> Event<'a> {
+        Event::new(fact.as_bytes(), parent)
+    }
+
+    /// Like `new`, for when only the parent's hash is at hand (e.g. when
+    /// rebuilding Events from storage): a Root if `parent_hash` is None,
+    /// otherwise a Node.
+    ///
+    /// ```
+    /// use pender::event::Event;
+    ///
+    /// let root = Event::new(b"potato", None);
+    /// let node = Event::new_from_parent_hash(b"leaf", Some(root.hash()));
+    /// assert_eq!(node, Event::new(b"leaf", Some(root)));
+    /// ```
+    pub fn new_from_parent_hash(fact: &'a [u8], parent_hash: Option<Blake2>) -> Event<'a> {
+        match parent_hash {
+            None => Event::Root { fact: fact },
+            Some(parent_hash) => Event::Node { fact: fact, parent_hash: parent_hash },
+        }
+    }
+}
+
+impl<'a, H: EventHash> Event<'a, H> {
+    /// Like `new`, for Events identified by a hash other than `Blake2`,
+    /// which can't be inferred from a missing parent.
+    pub fn with_parent(fact: &'a [u8], parent: Option<Event<H>>) -> Event<'a, H> {
         match parent {
             None =>
                 Event::Root { fact: fact },
@@ -43,45 +122,586 @@ impl<'a> Event<'a> {
         }
     }
 
+    /// Merge Event constructor.
+    ///
+    /// ```
+    /// use pender::event::Event;
+    ///
+    /// let root = Event::new(b"potato", None);
+    /// let parents = [Event::new(b"left", Some(root)).hash(),
+    ///                Event::new(b"right", Some(root)).hash()];
+    /// let merge = Event::new_merge(b"both", &parents);
+    /// assert_eq!(merge.parents(), &parents[..]);
+    /// assert_eq!(merge.parent(), Some(parents[0]));
+    /// ```
+    pub fn new_merge(fact: &'a [u8], parents: &'a [H]) -> Event<'a, H> {
+        Event::Merge { fact: fact, parents: parents }
+    }
+
     /// Blake2 hash of an Event.
     ///
-    /// In the case of a Node event, the hash is the same as that of the Fact.
-    /// For Nodes, the parent's hash is appended to the Fact, and we take the
-    /// Blake2 hash of that.
-    pub fn hash(self) -> Blake2 {
+    /// What is hashed starts with the (empty) domain, preceded by its
+    /// length as a big-endian u64 (see `hash_in_domain`), then the
+    /// variant's tag byte (as in `to_bytes`:
+    /// 0 for Root, 1 for Node, 2 for Merge), then the length of the Fact as
+    /// a big-endian u64, then the Fact itself; for a Root, that's all. For
+    /// Nodes, the parent's hash follows. Merges are hashed like Nodes, with
+    /// all the parent hashes appended in ascending byte order.
+    ///
+    /// The length keeps a Fact that ends in something resembling a parent
+    /// hash from colliding with a shorter Fact plus that parent, and the
+    /// tag keeps a Merge with fewer than two parents from colliding with
+    /// the Root or Node holding the same Fact. The domain's length is
+    /// written even when it's 0, so that no Event outside a domain shares
+    /// a preimage with one in a domain made to look like its tag and
+    /// length.
+    ///
+    /// Earlier versions left out all three, so every Event's hash has
+    /// changed, including those of Events with no domain. Files they wrote
+    /// have older version bytes, which `Fragment::read_from` and
+    /// `Fragment::read_compact` refuse rather than load with hashes that
+    /// no longer match.
+    pub fn hash(self) -> H {
+        self.hash_in_domain(b"")
+    }
+
+    /// Hash of the Event as stored in a Fragment with the given domain
+    /// (see `Fragment::new_with_domain`): the domain and its length come
+    /// before everything else `hash` covers, so no Event in one domain can
+    /// share a preimage with any Event in another. An empty domain gives
+    /// the same hash as `hash`.
+    pub fn hash_in_domain(self, domain: &[u8]) -> H {
+        self.hash_with(b"", domain)
+    }
+
+    /// Like `hash_in_domain`, hashed under `key` (see
+    /// `Fragment::with_key`). An empty key gives an unkeyed hash.
+    pub fn hash_with(self, key: &[u8], domain: &[u8]) -> H {
+        let domain_len = (domain.len() as u64).to_be_bytes();
+        let fact = self.fact();
+        let len = (fact.len() as u64).to_be_bytes();
         match self {
-            Event::Root { fact } => {
-                Blake2::new(fact)
+            Event::Root { .. } => {
+                H::hash_parts_keyed(key, &[&domain_len, domain, &[ROOT_TAG], &len, fact])
+            },
+            Event::Node { parent_hash, .. } => {
+                let parent_hash = parent_hash.as_ref();
+                H::hash_parts_keyed(key,
+                                    &[&domain_len, domain, &[NODE_TAG], &len, fact, parent_hash])
             },
-            Event::Node { fact, parent_hash } => {
-                let mut tmp = Vec::new();
-                tmp.extend(fact.iter().cloned());
-                tmp.extend(parent_hash.bytes.iter().cloned());
-                Blake2::new(&tmp)
+            Event::Merge { parents, .. } => {
+                let mut parents: Vec<&[u8]> = parents.iter()
+                    .map(|parent_hash| parent_hash.as_ref())
+                    .collect();
+                parents.sort();
+                let mut parts = vec![&domain_len[..], domain, &[MERGE_TAG], &len, fact];
+                parts.extend(parents);
+                H::hash_parts_keyed(key, &parts)
             },
         }
     }
 
     /// Return the hash value of the parent Event, if any.
-    pub fn parent(self) -> Option<Blake2> {
+    ///
+    /// For a Merge this is the first of its `parents`, which is the parent
+    /// that Chain traversal follows.
+    pub fn parent(self) -> Option<H> {
         match self {
             Event::Root { .. } => None,
             Event::Node { parent_hash, .. } => Some(parent_hash),
+            Event::Merge { parents, .. } => parents.first().cloned(),
+        }
+    }
+
+    /// Hash values of all the parent Events: none for a Root, one for a
+    /// Node, and any number for a Merge.
+    pub fn parents(&self) -> &[H] {
+        match *self {
+            Event::Root { .. } => &[],
+            Event::Node { ref parent_hash, .. } => slice::from_ref(parent_hash),
+            Event::Merge { parents, .. } => parents,
+        }
+    }
+
+    /// The Fact recorded by the Event, whatever its variant.
+    ///
+    /// ```
+    /// use pender::event::Event;
+    ///
+    /// let root = Event::new(b"potato", None);
+    /// let node = Event::new(b"leaf", Some(root));
+    /// assert_eq!(root.fact(), b"potato");
+    /// assert_eq!(node.fact(), b"leaf");
+    /// ```
+    pub fn fact(&self) -> &'a [u8] {
+        match *self {
+            Event::Root { fact } | Event::Node { fact, .. } | Event::Merge { fact, .. } => fact,
         }
     }
 
+    /// True if the two Events record the same Fact, whatever their parents
+    /// (or variants). `==` compares the parents too.
+    ///
+    /// ```
+    /// use pender::event::Event;
+    ///
+    /// let root = Event::new(b"potato", None);
+    /// assert!(root.same_fact(&Event::new(b"potato", Some(root))));
+    /// ```
+    pub fn same_fact(&self, other: &Event<H>) -> bool {
+        self.fact() == other.fact()
+    }
+
+    /// Rough size of the Event's data in bytes: the length of its Fact plus
+    /// that of each parent hash it names. For `Blake2`, that is 0 extra for
+    /// a Root, 64 for a Node and 64 per parent for a Merge. Doesn't count
+    /// the Event's own hash, or any overhead from storing it.
+    ///
+    /// ```
+    /// use pender::event::Event;
+    ///
+    /// let root = Event::new(b"potato", None);
+    /// assert_eq!(root.byte_len(), 6);
+    /// assert_eq!(Event::new(b"potato", Some(root)).byte_len(), 70);
+    /// ```
+    pub fn byte_len(&self) -> usize {
+        self.fact().len() + self.parents().iter().map(|parent| parent.as_ref().len()).sum::<usize>()
+    }
+
     /// True if the Event is Root, else false.
     pub fn is_root(self) -> bool {
         match self {
             Event::Root {..} => true,
-            Event::Node {..} => false,
+            Event::Node {..} | Event::Merge {..} => false,
+        }
+    }
+
+    /// Copy the Fact into an `OwnedEvent` with the same hash.
+    pub fn to_owned(self) -> OwnedEvent<H> {
+        match self {
+            Event::Root { fact } =>
+                OwnedEvent::Root { fact: fact.to_vec() },
+            Event::Node { fact, parent_hash } =>
+                OwnedEvent::Node { fact: fact.to_vec(), parent_hash: parent_hash },
+            Event::Merge { fact, parents } =>
+                OwnedEvent::Merge { fact: fact.to_vec(), parents: parents.to_vec() },
+        }
+    }
+}
+
+impl<'a> Event<'a> {
+    /// Encode the Event as a tag byte (0 for Root, 1 for Node, 2 for
+    /// Merge), then the parent hashes, then the length of the Fact as a
+    /// big-endian u64, then the Fact itself. A Node's parent is written as
+    /// its 64 bytes; a Merge's parents are preceded by their count as a
+    /// big-endian u64.
+    ///
+    /// ```
+    /// use pender::event::Event;
+    ///
+    /// let root = Event::new(b"potato", None);
+    /// let node = Event::new(b"leaf", Some(root));
+    /// assert_eq!(Event::from_bytes(&node.to_bytes()), Ok(node.to_owned()));
+    /// ```
+    pub fn to_bytes(self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        match self {
+            Event::Root { .. } => buf.push(ROOT_TAG),
+            Event::Node { parent_hash, .. } => {
+                buf.push(NODE_TAG);
+                buf.extend(parent_hash.bytes.iter().cloned());
+            },
+            Event::Merge { parents, .. } => {
+                buf.push(MERGE_TAG);
+                buf.extend((parents.len() as u64).to_be_bytes().iter().cloned());
+                for parent_hash in parents {
+                    buf.extend(parent_hash.bytes.iter().cloned());
+                }
+            },
+        }
+        let fact = self.fact();
+        buf.extend((fact.len() as u64).to_be_bytes().iter().cloned());
+        buf.extend(fact.iter().cloned());
+        buf
+    }
+
+    /// Decode an Event encoded by `to_bytes`. The buffer must contain
+    /// exactly one Event.
+    ///
+    /// Like `Fragment::read_from`, this never panics on malformed input.
+    pub fn from_bytes(buf: &[u8]) -> Result<OwnedEvent, DecodeError> {
+        let (event, used) = decode(buf)?;
+        if used != buf.len() {
+            return Err(DecodeError::TrailingBytes(buf.len() - used));
+        }
+        Ok(event)
+    }
+}
+
+/// Shows the variant, the Fact (as a string if it is UTF-8, otherwise as
+/// hex) and the first eight hex digits of each parent hash.
+///
+/// ```
+/// use pender::event::Event;
+///
+/// let root = Event::new(b"potato", None);
+/// assert_eq!(format!("{:?}", root), "Root { fact: \"potato\" }");
+/// ```
+impl<'a, H: EventHash> fmt::Debug for Event<'a, H> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Event::Root { fact } => f.debug_struct("Root")
+                .field("fact", &DebugFact(fact))
+                .finish(),
+            Event::Node { fact, parent_hash } => f.debug_struct("Node")
+                .field("fact", &DebugFact(fact))
+                .field("parent_hash", &ShortHash(parent_hash))
+                .finish(),
+            Event::Merge { fact, parents } => {
+                let parents: Vec<ShortHash<H>> = parents.iter().cloned().map(ShortHash).collect();
+                f.debug_struct("Merge")
+                    .field("fact", &DebugFact(fact))
+                    .field("parents", &parents)
+                    .finish()
+            },
+        }
+    }
+}
+
+/// A Fact, quoted if it is UTF-8 and otherwise as `0x` and lowercase hex.
+struct DebugFact<'a>(&'a [u8]);
+
+impl<'a> fmt::Debug for DebugFact<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match str::from_utf8(self.0) {
+            Ok(s) => write!(f, "{:?}", s),
+            Err(_) => {
+                f.write_str("0x")?;
+                self.0.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+            },
+        }
+    }
+}
+
+/// The first eight hex digits of a hash (all of them, for shorter hashes).
+pub(crate) struct ShortHash<H>(pub(crate) H);
+
+impl<H: EventHash> fmt::Debug for ShortHash<H> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0.short_hex(8))
+    }
+}
+
+/// The Event's hash, so an Event can be passed where a hash is expected.
+///
+/// ```
+/// use pender::event::Event;
+/// use pender::hash::Blake2;
+///
+/// let root = Event::new(b"potato", None);
+/// let hash: Blake2 = root.into();
+/// assert_eq!(hash, root.hash());
+/// ```
+impl<'a, const N: usize> From<Event<'a, Blake2b<N>>> for Blake2b<N> {
+    fn from(event: Event<'a, Blake2b<N>>) -> Blake2b<N> {
+        event.hash()
+    }
+}
+
+impl<'a> From<Event<'a, Blake2s>> for Blake2s {
+    fn from(event: Event<'a, Blake2s>) -> Blake2s {
+        event.hash()
+    }
+}
+
+pub(crate) const ROOT_TAG: u8 = 0;
+pub(crate) const NODE_TAG: u8 = 1;
+pub(crate) const MERGE_TAG: u8 = 2;
+
+/// Length of the shortest encoded Event: a Root's tag and Fact length.
+#[cfg(feature = "std")]
+pub(crate) const MIN_ENCODED_LEN: usize = 1 + 8;
+
+/// Reasons a buffer could not be decoded into an Event.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DecodeError {
+    /// The buffer ended before the Event did.
+    Truncated,
+    /// The first byte was not a known Event tag.
+    BadTag(u8),
+    /// The buffer held this many bytes after the end of the Event.
+    TrailingBytes(usize),
+}
+
+/// Decode one Event from the front of `buf`, returning it along with the
+/// number of bytes it occupied.
+pub(crate) fn decode(buf: &[u8]) -> Result<(OwnedEvent, usize), DecodeError> {
+    let (tag, parents, fact) = decode_parts(buf)?;
+    let used = fact.end;
+    let fact = buf[fact].to_vec();
+    let event = match tag {
+        ROOT_TAG => OwnedEvent::Root { fact: fact },
+        NODE_TAG => OwnedEvent::Node { fact: fact, parent_hash: parents[0] },
+        _ => OwnedEvent::Merge { fact: fact, parents: parents },
+    };
+    Ok((event, used))
+}
+
+/// Decode the tag and parents of the Event at the front of `buf`, and find
+/// where in `buf` its Fact lies, without copying the Fact. The Event ends
+/// where the Fact does.
+pub(crate) fn decode_parts(buf: &[u8]) -> Result<(u8, Vec<Blake2>, Range<usize>), DecodeError> {
+    let mut reader = Reader::new(buf);
+    let tag = reader.take(1)?[0];
+    let parents = match tag {
+        ROOT_TAG => Vec::new(),
+        NODE_TAG => vec![reader.take_hash()?],
+        MERGE_TAG => {
+            let count = reader.take_len(64)?;
+            let mut parents = Vec::with_capacity(count);
+            for _ in 0..count {
+                parents.push(reader.take_hash()?);
+            }
+            parents
+        },
+        _ => return Err(DecodeError::BadTag(tag)),
+    };
+
+    let len = reader.take_len(1)?;
+    let start = reader.pos;
+    reader.take(len)?;
+    Ok((tag, parents, start..reader.pos))
+}
+
+/// Bounds-checked cursor over a byte slice.
+pub(crate) struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(buf: &'a [u8]) -> Reader<'a> {
+        Reader { buf: buf, pos: 0 }
+    }
+
+    pub(crate) fn remaining(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    pub(crate) fn take(&mut self, n: usize) -> Result<&'a [u8], DecodeError> {
+        if n > self.remaining() {
+            return Err(DecodeError::Truncated);
+        }
+        let bytes = &self.buf[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    pub(crate) fn take_hash(&mut self) -> Result<Blake2, DecodeError> {
+        let mut bytes = [0u8; 64];
+        bytes.copy_from_slice(self.take(64)?);
+        Ok(Blake2 { bytes: bytes })
+    }
+
+    /// Read a big-endian u64 count of items `item_size` bytes long, failing
+    /// if the rest of the buffer is too short to hold them.
+    pub(crate) fn take_len(&mut self, item_size: usize) -> Result<usize, DecodeError> {
+        let mut len = [0u8; 8];
+        len.copy_from_slice(self.take(8)?);
+        let len = u64::from_be_bytes(len);
+        if len > (self.remaining() / item_size) as u64 {
+            return Err(DecodeError::Truncated);
+        }
+        Ok(len as usize)
+    }
+}
+
+/// An Event that owns its Fact, for when the Fact's bytes can't be kept
+/// alive for as long as the Fragment (e.g. facts read from a socket).
+///
+/// Hashes identically to the equivalent borrowed `Event`.
+///
+/// ```
+/// use pender::event::Event;
+///
+/// let root = Event::new(b"potato", None);
+/// let owned = root.to_owned();
+/// assert_eq!(owned.hash(), root.hash());
+/// assert_eq!(owned.as_borrowed(), root);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum OwnedEvent<H: EventHash = Blake2> {
+    Root {
+        #[cfg_attr(feature = "serde", serde(with = "serde_fact"))]
+        fact: Vec<u8>,
+    },
+    Node {
+        #[cfg_attr(feature = "serde", serde(with = "serde_fact"))]
+        fact: Vec<u8>,
+        parent_hash: H,
+    },
+    Merge {
+        #[cfg_attr(feature = "serde", serde(with = "serde_fact"))]
+        fact: Vec<u8>,
+        parents: Vec<H>,
+    },
+}
+
+impl<H: EventHash> OwnedEvent<H> {
+    /// Borrow the Fact, giving an `Event` that can be put in a Fragment.
+    pub fn as_borrowed<'a>(&'a self) -> Event<'a, H> {
+        match *self {
+            OwnedEvent::Root { ref fact } =>
+                Event::Root { fact: fact },
+            OwnedEvent::Node { ref fact, parent_hash } =>
+                Event::Node { fact: fact, parent_hash: parent_hash },
+            OwnedEvent::Merge { ref fact, ref parents } =>
+                Event::Merge { fact: fact, parents: parents },
+        }
+    }
+
+    /// Blake2 hash of the Event (see `Event::hash`).
+    pub fn hash(&self) -> H {
+        self.as_borrowed().hash()
+    }
+
+    /// Return the hash value of the parent Event, if any.
+    pub fn parent(&self) -> Option<H> {
+        self.as_borrowed().parent()
+    }
+
+    /// Hash values of all the parent Events (see `Event::parents`).
+    pub fn parents(&self) -> &[H] {
+        match *self {
+            OwnedEvent::Root { .. } => &[],
+            OwnedEvent::Node { ref parent_hash, .. } => slice::from_ref(parent_hash),
+            OwnedEvent::Merge { ref parents, .. } => parents,
+        }
+    }
+
+    /// The Fact recorded by the Event (see `Event::fact`).
+    pub fn fact(&self) -> &[u8] {
+        match *self {
+            OwnedEvent::Root { ref fact }
+            | OwnedEvent::Node { ref fact, .. }
+            | OwnedEvent::Merge { ref fact, .. } => fact,
+        }
+    }
+
+    /// True if the Event is Root, else false.
+    pub fn is_root(&self) -> bool {
+        self.as_borrowed().is_root()
+    }
+}
+
+impl OwnedEvent {
+    /// Encode the Event (see `Event::to_bytes`).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.as_borrowed().to_bytes()
+    }
+}
+
+/// An Event that remembers its hash once computed, so that repeated
+/// `hash` calls (e.g. keying a Fragment and then checking it) only pay for
+/// the digest once.
+///
+/// ```
+/// use pender::event::{CachedEvent, Event};
+///
+/// let root = Event::new(b"potato", None);
+/// let cached = CachedEvent::new(root);
+/// assert_eq!(cached.hash(), root.hash());
+/// assert_eq!(cached.event(), root);
+/// ```
+#[derive(Clone, Debug)]
+pub struct CachedEvent<'a, H: EventHash = Blake2> {
+    event: Event<'a, H>,
+    hash: OnceCell<H>,
+}
+
+impl<'a, H: EventHash> CachedEvent<'a, H> {
+    pub fn new(event: Event<'a, H>) -> CachedEvent<'a, H> {
+        CachedEvent { event: event, hash: OnceCell::new() }
+    }
+
+    /// The wrapped Event.
+    pub fn event(&self) -> Event<'a, H> {
+        self.event
+    }
+
+    /// Blake2 hash of the Event (see `Event::hash`), computed on the first
+    /// call only.
+    pub fn hash(&self) -> H {
+        *self.hash.get_or_init(|| self.event.hash())
+    }
+}
+
+impl<'a, H: EventHash> From<Event<'a, H>> for CachedEvent<'a, H> {
+    fn from(event: Event<'a, H>) -> CachedEvent<'a, H> {
+        CachedEvent::new(event)
+    }
+}
+
+/// Serde representation of Facts: a string in human-readable formats if the
+/// Fact is valid UTF-8, otherwise bytes.
+#[cfg(feature = "serde")]
+pub(crate) mod serde_fact {
+    use core::fmt;
+    use core::str;
+
+    use serde::{Deserializer, Serializer};
+    use serde::de::{Error, SeqAccess, Visitor};
+    #[cfg(not(feature = "std"))]
+    use prelude::*;
+
+    pub fn serialize<T, S>(fact: &T, serializer: S) -> Result<S::Ok, S::Error>
+        where T: AsRef<[u8]>, S: Serializer
+    {
+        let fact = fact.as_ref();
+        match str::from_utf8(fact) {
+            Ok(s) if serializer.is_human_readable() => serializer.serialize_str(s),
+            _ => serializer.serialize_bytes(fact),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        deserializer.deserialize_bytes(FactVisitor)
+    }
+
+    struct FactVisitor;
+
+    impl<'de> Visitor<'de> for FactVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a string or a byte array")
+        }
+
+        fn visit_str<E: Error>(self, v: &str) -> Result<Vec<u8>, E> {
+            Ok(v.as_bytes().to_vec())
+        }
+
+        fn visit_bytes<E: Error>(self, v: &[u8]) -> Result<Vec<u8>, E> {
+            Ok(v.to_vec())
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u8>, A::Error> {
+            let mut fact = Vec::new();
+            while let Some(byte) = seq.next_element()? {
+                fact.push(byte);
+            }
+            Ok(fact)
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Event;
+    use super::{CachedEvent, DecodeError, Event, MERGE_TAG, NODE_TAG, ROOT_TAG};
+    use hash::{Blake2, Blake2b, Blake2s, CountingHash};
+    #[cfg(feature = "serde")]
+    use super::OwnedEvent;
 
     #[test]
     fn hash_root_self_equal() {
@@ -129,4 +749,371 @@ mod tests {
         let desc_b = Event::new(b"foo", Some(root_b));
         assert!(desc_a.hash() != desc_b.hash());
     }
+
+    #[test]
+    fn roots_equal_when_facts_equal() {
+        let root_a = Event::new(b"foo", None);
+        let root_b = Event::new(b"foo", None);
+        assert_eq!(root_a, root_b);
+        assert_eq!(root_a.hash(), root_b.hash());
+    }
+
+    #[test]
+    fn roots_unequal_when_facts_unequal() {
+        assert!(Event::new(b"foo", None) != Event::new(b"boo", None));
+    }
+
+    #[test]
+    fn root_not_equal_to_descendent_with_same_fact() {
+        let root = Event::new(b"foo", None);
+        let descendent = Event::new(b"foo", Some(root));
+        assert!(root != descendent);
+    }
+
+    #[test]
+    fn descendents_equal_when_facts_and_parents_equal() {
+        let root = Event::new(b"foo", None);
+        let desc_a = Event::new(b"foo", Some(root));
+        let desc_b = Event::new(b"foo", Some(root));
+        assert_eq!(desc_a, desc_b);
+        assert_eq!(desc_a.hash(), desc_b.hash());
+    }
+
+    #[test]
+    fn descendents_unequal_when_facts_unequal() {
+        let root = Event::new(b"foo", None);
+        assert!(Event::new(b"foo", Some(root)) != Event::new(b"bar", Some(root)));
+    }
+
+    #[test]
+    fn descendents_unequal_when_parents_unequal() {
+        let root_a = Event::new(b"foo", None);
+        let root_b = Event::new(b"bar", None);
+        assert!(Event::new(b"foo", Some(root_a)) != Event::new(b"foo", Some(root_b)));
+    }
+
+    #[test]
+    fn same_fact_ignores_parents() {
+        let root_a = Event::new(b"foo", None);
+        let root_b = Event::new(b"bar", None);
+        let node_a = Event::new(b"foo", Some(root_a));
+        let node_b = Event::new(b"foo", Some(root_b));
+        assert!(node_a.same_fact(&node_b));
+        assert!(node_a != node_b);
+        assert!(node_a.same_fact(&root_a));
+        assert!(!node_a.same_fact(&root_b));
+    }
+
+    #[test]
+    fn node_byte_len_counts_its_parent_hash() {
+        let root = Event::new(b"foo", None);
+        let node = Event::new(b"foo", Some(root));
+        assert_eq!(root.byte_len(), 3);
+        assert_eq!(node.byte_len(), root.byte_len() + 64);
+        let parents = [root.hash(), node.hash()];
+        assert_eq!(Event::new_merge(b"foo", &parents).byte_len(), 3 + 128);
+    }
+
+    #[test]
+    fn owned_event_from_cloned_buffer_hashes_equal_to_borrowed() {
+        let buffer = b"foo".to_vec();
+        let root = Event::new(&buffer, None);
+        let (owned_root, owned_node) = {
+            let copy = buffer.clone();
+            (Event::new(&copy, None).to_owned(),
+             Event::new(&copy, Some(root)).to_owned())
+        };
+
+        assert_eq!(owned_root.hash(), root.hash());
+        assert!(owned_root.is_root());
+        assert_eq!(owned_node.hash(), Event::new(&buffer, Some(root)).hash());
+        assert_eq!(owned_node.parent(), Some(root.hash()));
+        assert!(!owned_node.is_root());
+    }
+
+    #[test]
+    fn owned_event_round_trips_through_borrowed() {
+        let root = Event::new(b"foo", None);
+        let node = Event::new(b"bar", Some(root));
+        assert_eq!(root.to_owned().as_borrowed(), root);
+        assert_eq!(node.to_owned().as_borrowed(), node);
+    }
+
+    #[test]
+    fn root_round_trips_through_bytes() {
+        let root = Event::new(b"foo", None);
+        let bytes = root.to_bytes();
+        assert_eq!(bytes.len(), 1 + 8 + 3);
+        assert_eq!(bytes[0], 0);
+        assert_eq!(Event::from_bytes(&bytes), Ok(root.to_owned()));
+    }
+
+    #[test]
+    fn node_round_trips_through_bytes() {
+        let root = Event::new(b"foo", None);
+        let node = Event::new(b"bar", Some(root));
+        let bytes = node.to_bytes();
+        assert_eq!(bytes.len(), 1 + 64 + 8 + 3);
+        assert_eq!(bytes[0], 1);
+        let decoded = Event::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.hash(), node.hash());
+        assert_eq!(decoded.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn empty_fact_round_trips_through_bytes() {
+        let root = Event::new(b"", None);
+        assert_eq!(Event::from_bytes(&root.to_bytes()), Ok(root.to_owned()));
+    }
+
+    #[test]
+    fn truncated_buffers_are_rejected() {
+        let root = Event::new(b"foo", None);
+        let bytes = Event::new(b"bar", Some(root)).to_bytes();
+        for len in 0..bytes.len() {
+            assert_eq!(Event::from_bytes(&bytes[..len]), Err(DecodeError::Truncated));
+        }
+    }
+
+    #[test]
+    fn oversized_length_prefix_is_rejected() {
+        let mut bytes = vec![0u8];
+        bytes.extend([0xFFu8; 8].iter().cloned());
+        bytes.extend(b"foo".iter().cloned());
+        assert_eq!(Event::from_bytes(&bytes), Err(DecodeError::Truncated));
+    }
+
+    #[test]
+    fn unknown_tag_and_trailing_bytes_are_rejected() {
+        let mut bytes = Event::new(b"foo", None).to_bytes();
+        bytes.push(0);
+        assert_eq!(Event::from_bytes(&bytes), Err(DecodeError::TrailingBytes(1)));
+        bytes[0] = 7;
+        assert_eq!(Event::from_bytes(&bytes), Err(DecodeError::BadTag(7)));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn owned_event_round_trips_binary_fact_through_json() {
+        let root = Event::new(b"\xFF\x00\"", None);
+        let node = Event::new(b"bar", Some(root));
+
+        let json = ::serde_json::to_string(&root).unwrap();
+        let decoded: OwnedEvent = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, root.to_owned());
+
+        let json = ::serde_json::to_string(&node.to_owned()).unwrap();
+        assert_eq!(json, ::serde_json::to_string(&node).unwrap());
+        let decoded: Event = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, node);
+    }
+
+    #[test]
+    fn merge_hash_is_independent_of_parent_order() {
+        let root = Event::new(b"foo", None);
+        let left = Event::new(b"left", Some(root)).hash();
+        let right = Event::new(b"right", Some(root)).hash();
+        let third = Event::new(b"third", Some(root)).hash();
+
+        let forwards = [left, right, third];
+        let backwards = [third, right, left];
+        assert_eq!(Event::new_merge(b"merge", &forwards).hash(),
+                   Event::new_merge(b"merge", &backwards).hash());
+    }
+
+    #[test]
+    fn two_parent_merge_differs_from_either_node() {
+        let root = Event::new(b"foo", None);
+        let left = Event::new(b"left", Some(root));
+        let right = Event::new(b"right", Some(root));
+        let parents = [left.hash(), right.hash()];
+        let merge = Event::new_merge(b"merge", &parents);
+
+        assert!(merge.hash() != Event::new(b"merge", Some(left)).hash());
+        assert!(merge.hash() != Event::new(b"merge", Some(right)).hash());
+    }
+
+    #[test]
+    fn parents_of_each_variant() {
+        let root = Event::new(b"foo", None);
+        let node = Event::new(b"bar", Some(root));
+        let parents = [root.hash(), node.hash()];
+        let merge = Event::new_merge(b"baz", &parents);
+
+        assert!(root.parents().is_empty());
+        assert_eq!(node.parents(), &[root.hash()]);
+        assert_eq!(merge.parents(), &parents);
+        assert_eq!(merge.parent(), Some(root.hash()));
+        assert!(!merge.is_root());
+        assert_eq!(merge.to_owned().parents(), &parents);
+    }
+
+    #[test]
+    fn merge_round_trips_through_bytes() {
+        let root = Event::new(b"foo", None);
+        let parents = [root.hash(), Event::new(b"bar", Some(root)).hash()];
+        let merge = Event::new_merge(b"baz", &parents);
+        let bytes = merge.to_bytes();
+        assert_eq!(bytes.len(), 1 + 8 + 2 * 64 + 8 + 3);
+        assert_eq!(Event::from_bytes(&bytes), Ok(merge.to_owned()));
+        for len in 0..bytes.len() {
+            assert_eq!(Event::from_bytes(&bytes[..len]), Err(DecodeError::Truncated));
+        }
+    }
+
+    fn concatenated_hash(tag: u8, fact: &[u8], parents: &[Blake2]) -> Blake2 {
+        let mut tmp = vec![0; 8];
+        tmp.push(tag);
+        tmp.extend((fact.len() as u64).to_be_bytes().iter().cloned());
+        tmp.extend(fact.iter().cloned());
+        for parent_hash in parents {
+            tmp.extend(parent_hash.bytes.iter().cloned());
+        }
+        Blake2::new(&tmp)
+    }
+
+    #[test]
+    fn node_hash_equals_hash_of_concatenation() {
+        let root = Event::new(b"foo", None);
+        let node = Event::new(b"bar", Some(root));
+        let empty = Event::new(b"", Some(node));
+        assert_eq!(root.hash(), concatenated_hash(ROOT_TAG, b"foo", &[]));
+        assert_eq!(node.hash(), concatenated_hash(NODE_TAG, b"bar", &[root.hash()]));
+        assert_eq!(empty.hash(), concatenated_hash(NODE_TAG, b"", &[node.hash()]));
+    }
+
+    #[test]
+    fn debug_shows_variant_fact_and_parents() {
+        let root = Event::new(b"foo", None);
+        let node = Event::new(b"bar", Some(root));
+        let parent = &root.hash().to_hex()[..8];
+        assert_eq!(format!("{:?}", node),
+                   format!("Node {{ fact: \"bar\", parent_hash: {} }}", parent));
+
+        let parents = [root.hash()];
+        let merge = Event::new_merge(&[0xff, 0x00], &parents);
+        assert_eq!(format!("{:?}", merge),
+                   format!("Merge {{ fact: 0xff00, parents: [{}] }}", parent));
+    }
+
+    #[test]
+    fn debug_shows_whole_hash_when_shorter_than_eight_digits() {
+        let parent_hash = Blake2b::<2>::new(b"foo");
+        let node = Event::Node { fact: b"bar", parent_hash: parent_hash };
+        assert_eq!(format!("{:?}", node),
+                   format!("Node {{ fact: \"bar\", parent_hash: {} }}", parent_hash.to_hex()));
+    }
+
+    #[test]
+    fn new_from_parent_hash_matches_new() {
+        let root = Event::new(b"foo", None);
+        assert_eq!(Event::new_from_parent_hash(b"foo", None), root);
+        assert_eq!(Event::new_from_parent_hash(b"bar", Some(root.hash())),
+                   Event::new(b"bar", Some(root)));
+    }
+
+    #[test]
+    fn hash_in_domain_prefixes_domain() {
+        let root = Event::new(b"foo", None);
+        let node = Event::new(b"bar", Some(root));
+        assert_eq!(root.hash_in_domain(b""), root.hash());
+        assert_eq!(node.hash_in_domain(b""), node.hash());
+        assert_eq!(root.hash_in_domain(b"db"),
+                   Blake2::new(b"\0\0\0\0\0\0\0\x02db\0\0\0\0\0\0\0\0\x03foo"));
+        assert_ne!(root.hash_in_domain(b"one"), root.hash_in_domain(b"two"));
+    }
+
+    #[test]
+    fn events_in_different_domains_do_not_collide() {
+        // Were the domain appended, a Merge's last parent could instead be
+        // read as the start of the domain of a Merge with one parent fewer.
+        let root = Event::new(b"root", None);
+        let mut parents = [Event::new(b"a", Some(root)).hash(),
+                           Event::new(b"b", Some(root)).hash()];
+        parents.sort();
+        let mut longer = parents[1].bytes.to_vec();
+        longer.extend_from_slice(b"db");
+        let two = Event::new_merge(b"merge", &parents);
+        let one = Event::new_merge(b"merge", &parents[..1]);
+        assert_ne!(two.hash_in_domain(b"db"), one.hash_in_domain(&longer));
+
+        // Likewise a Root's domain and a Node's parent hash.
+        let node = Event::new(b"fact", Some(root));
+        let as_root = Event::new(b"fact", None);
+        assert_ne!(as_root.hash_in_domain(&root.hash().bytes), node.hash());
+    }
+
+    #[test]
+    fn merge_hash_equals_hash_of_sorted_concatenation() {
+        let root = Event::new(b"foo", None);
+        let mut parents = [Event::new(b"a", Some(root)).hash(),
+                           Event::new(b"b", Some(root)).hash(),
+                           root.hash()];
+        let hash = Event::new_merge(b"merge", &parents).hash();
+        parents.sort_by_key(|parent_hash| parent_hash.bytes);
+        assert_eq!(hash, concatenated_hash(MERGE_TAG, b"merge", &parents));
+    }
+
+    #[test]
+    fn cached_event_hashes_once() {
+        let root: Event<CountingHash> = Event::with_parent(b"foo", None);
+        let cached = CachedEvent::new(root);
+        let before = CountingHash::computed();
+        assert_eq!(cached.hash(), root.hash());
+        assert_eq!(cached.hash(), cached.hash());
+        // One for the cache, one for the uncached `root.hash()` above.
+        assert_eq!(CountingHash::computed() - before, 2);
+    }
+
+    #[test]
+    fn empty_root_hash_is_pinned() {
+        // The unkeyed Blake2b-512 of the domain's length, 0, as a u64, the
+        // Root tag, 0, and the Fact's length, 0, as a u64.
+        assert_eq!(Event::new(b"", None).hash().to_hex(),
+                   "81bfc9b3f2de772a911615bbcc50a2e92c2b551197d6de28fdae592a3c512488\
+                    9400844ca73a35ebd48e0bc9f1290c6245b5cf753976f1abe4f3470936c4ea40");
+    }
+
+    #[test]
+    fn fact_ending_in_parent_hash_does_not_collide() {
+        let parent = Event::new(b"parent", None);
+        let node = Event::new(b"fact", Some(parent));
+
+        // Without the length tag, both hashed b"fact" followed by the
+        // parent's hash.
+        let mut crafted = b"fact".to_vec();
+        crafted.extend(parent.hash().bytes.iter().cloned());
+        let root = Event::new(&crafted, None);
+        assert_ne!(root.hash(), node.hash());
+    }
+
+    #[test]
+    fn merge_with_too_few_parents_does_not_collide() {
+        let root = Event::new(b"foo", None);
+        let node = Event::new(b"foo", Some(root));
+        let parent = [root.hash()];
+        assert_ne!(Event::<Blake2>::new_merge(b"foo", &[]).hash(), root.hash());
+        assert_ne!(Event::new_merge(b"foo", &parent).hash(), node.hash());
+    }
+
+    #[test]
+    fn empty_node_hashes_differently_from_parent() {
+        let root = Event::new(b"", None);
+        let node = Event::new(b"", Some(root));
+        let grandchild = Event::new(b"", Some(node));
+        assert_ne!(node.hash(), root.hash());
+        assert_ne!(grandchild.hash(), node.hash());
+        assert_eq!(node.parent(), Some(root.hash()));
+    }
+
+    #[test]
+    fn blake2_from_event_is_its_hash() {
+        let root = Event::new(b"foo", None);
+        let node = Event::new(b"bar", Some(root));
+        assert_eq!(Blake2::from(root), root.hash());
+        assert_eq!(Blake2::from(node), node.hash());
+
+        let root: Event<Blake2s> = Event::with_parent(b"foo", None);
+        assert_eq!(Blake2s::from(root), root.hash());
+    }
 }
@@ -1,7 +1,29 @@
-use std::collections::HashMap;
+use core::fmt;
+use core::iter::FromIterator;
+use core::mem;
+#[cfg(feature = "std")]
+use std::io::{self, Read, Write};
+#[cfg(feature = "mmap")]
+use std::path::Path;
+#[cfg(feature = "std")]
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use event::Event;
-use hash::Blake2;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use bloom::BloomFilter;
+use collections::{map, map_with_capacity, set_with_capacity, Map, Set, VecDeque};
+#[cfg(feature = "serde")]
+use event::serde_fact;
+use event::{CachedEvent, Event, OwnedEvent, ShortHash};
+#[cfg(feature = "std")]
+use event::{self, DecodeError};
+use hash::{Blake2, EventHash};
+#[cfg(feature = "mmap")]
+use mmap::MappedFragment;
+#[cfg(not(feature = "std"))]
+use prelude::*;
+use summary::Summary;
 
 /// A Pender database fragment.
 /// 
@@ -19,80 +41,4257 @@ use hash::Blake2;
 ///
 /// let stuff = b"Stuff happened";
 /// let root = Event::new(stuff, None);
-/// frag.append(stuff);
+/// frag.append(stuff).unwrap();
 /// assert_eq!(frag.head, Some(root));
 ///
 /// let new_event = Event::new(b"More stuff happened", Some(root));
-/// frag.append_event(new_event);
+/// frag.append_event(new_event).unwrap();
 /// assert_eq!(frag.head, Some(new_event));
 ///
 /// let mut chain = frag.summarize("my-summary");
 /// assert_eq!(chain.next_event(), Link::Event(new_event));
 /// assert_eq!(chain.next_event(), Link::Event(root));
 /// assert_eq!(chain.next_event(), Link::Terminus(None));
+///
+/// // The Chain only borrowed the Fragment.
+/// frag.append(b"Yet more stuff happened").unwrap();
+/// assert_eq!(frag.len(), 3);
+/// ```
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(bound(deserialize = "H: Deserialize<'de>")))]
+pub struct Fragment<'a, H: EventHash = Blake2> {
+    #[cfg_attr(feature = "serde", serde(borrow))]
+    pub head: Option<Event<'a, H>>,
+    #[cfg_attr(feature = "serde", serde(borrow))]
+    pub events: Map<H, Event<'a, H>>,
+    /// When each Event was first appended, in milliseconds since the Unix
+    /// epoch. Kept apart from the Events so it doesn't affect their hashes.
+    #[cfg_attr(feature = "serde", serde(default))]
+    timestamps: Map<H, u64>,
+    /// Tips of the named branches other than `DEFAULT_BRANCH`, whose tip is
+    /// `head`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    branches: Map<String, H>,
+    /// Largest Fact the `try_` methods accept, in bytes; 0 for no limit.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    max_fact_size: usize,
+    /// Set by `seal`, after which nothing more can be appended.
+    #[cfg_attr(feature = "serde", serde(default))]
+    sealed: bool,
+    /// Folded into the hash of every Event; empty for none.
+    #[cfg_attr(feature = "serde", serde(borrow, default, serialize_with = "serde_fact::serialize"))]
+    domain: &'a [u8],
+    /// Blake2 key every Event is hashed under; empty for none. Secret, so
+    /// never serialized.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    key: Vec<u8>,
+    /// Summaries attached with `add_summary`, oldest first.
+    #[cfg_attr(feature = "serde", serde(borrow, default))]
+    summaries: Vec<Summary<'a, H>>,
+    /// Callbacks registered with `on_append`, in registration order.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    hooks: Vec<AppendHook<H>>,
+}
+
+/// A callback run on every Event appended to a Fragment, with the hash the
+/// Event is stored under (see `Fragment::on_append`).
+///
+/// The callback is stored in the Fragment, so its bounds decide what the
+/// Fragment can do. It must be `Send` and `Sync`, or the Fragment could no
+/// longer be sent to or shared with other threads (see `SharedFragment`);
+/// share state with it through `Arc` and `Mutex` rather than `Rc` and
+/// `RefCell`. It must be `'static`, or it would tie the Fragment to
+/// whatever it borrows.
+pub type AppendHook<H = Blake2> = Box<dyn FnMut(&Event<H>, &H) + Send + Sync>;
+
+impl<'a, H: EventHash> Default for Fragment<'a, H> {
+    fn default() -> Fragment<'a, H> {
+        Fragment {
+            head: None,
+            events: Map::new(),
+            timestamps: Map::new(),
+            branches: Map::new(),
+            max_fact_size: 0,
+            sealed: false,
+            domain: b"",
+            key: Vec::new(),
+            summaries: Vec::new(),
+            hooks: Vec::new(),
+        }
+    }
+}
+
+/// Clones everything but the `on_append` callbacks, which can't be cloned;
+/// the clone starts with none.
+impl<'a, H: EventHash> Clone for Fragment<'a, H> {
+    fn clone(&self) -> Fragment<'a, H> {
+        Fragment {
+            head: self.head,
+            events: self.events.clone(),
+            timestamps: self.timestamps.clone(),
+            branches: self.branches.clone(),
+            max_fact_size: self.max_fact_size,
+            sealed: self.sealed,
+            domain: self.domain,
+            key: self.key.clone(),
+            summaries: self.summaries.clone(),
+            hooks: Vec::new(),
+        }
+    }
+}
+
+/// Fragments are equal if they store Events with the same hashes and have
+/// the same head. Timestamps, branches, summaries, the fact size limit and
+/// whether they are sealed are ignored.
+impl<'a, 'b, H: EventHash> PartialEq<Fragment<'b, H>> for Fragment<'a, H> {
+    fn eq(&self, other: &Fragment<'b, H>) -> bool {
+        self.head.map(|head| self.hash_of(head)) == other.head.map(|head| other.hash_of(head))
+            && self.len() == other.len()
+            && self.events.keys().all(|hash| other.contains(hash))
+    }
+}
+
+impl<'a, H: EventHash> Eq for Fragment<'a, H> { }
+
+/// Shows the head's short hash, `len`, `depth` and the Events keyed by
+/// short hash, sorted by hash so that equal Fragments always print the
+/// same. Timestamps, branches, summaries, the key and `on_append`
+/// callbacks are left out.
+///
 /// ```
-#[derive(Clone, Debug, Default)]
-pub struct Fragment<'a> {
-    pub head: Option<Event<'a>>,
-    pub events: HashMap<Blake2, Event<'a>>,
+/// use pender::fragment::Fragment;
+///
+/// let mut frag = Fragment::new();
+/// frag.append(b"one").unwrap();
+/// let shown = format!("{:?}", frag);
+/// assert!(shown.starts_with("Fragment { head: Some("));
+/// assert!(shown.contains("len: 1, depth: 1, events: {"));
+/// ```
+impl<'a, H: EventHash> fmt::Debug for Fragment<'a, H> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Fragment")
+            .field("head", &self.head.map(|head| ShortHash(self.hash_of(head))))
+            .field("len", &self.len())
+            .field("depth", &self.depth())
+            .field("events", &SortedEvents(&self.events))
+            .finish()
+    }
 }
 
+/// A Fragment's Events, shown as a map sorted by hash.
+struct SortedEvents<'f, 'a: 'f, H: EventHash>(&'f Map<H, Event<'a, H>>);
+
+impl<'f, 'a, H: EventHash> fmt::Debug for SortedEvents<'f, 'a, H> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut events: Vec<(&H, &Event<'a, H>)> = self.0.iter().collect();
+        events.sort_by_key(|&(hash, _)| *hash);
+        f.debug_map()
+            .entries(events.into_iter().map(|(&hash, event)| (ShortHash(hash), event)))
+            .finish()
+    }
+}
+
+/// The branch whose tip is a Fragment's `head`, and which `append` extends.
+pub const DEFAULT_BRANCH: &str = "main";
+
 impl<'a> Fragment<'a> {
+    /// An empty Fragment of `Blake2`-hashed Events. For another
+    /// `EventHash`, use `Fragment::default()`.
     pub fn new() -> Fragment<'a> { Default::default() }
 
-    pub fn append(&mut self, fact: &'a [u8]) { 
-        let head = self.head;
-        self.append_event(Event::new(fact, head));
+    /// An empty Fragment with room for `n` Events before it reallocates.
+    pub fn with_capacity(n: usize) -> Fragment<'a> {
+        Fragment {
+            head: None,
+            events: map_with_capacity(n),
+            timestamps: map_with_capacity(n),
+            ..Default::default()
+        }
     }
 
-    pub fn append_event (&mut self, event: Event<'a>) { 
-        self.head = Some(event);
-        self.events.insert(event.hash(), event);
+    /// An empty Fragment whose Events are hashed with `domain` folded in
+    /// (see `Event::hash_in_domain`), so that they can never share a hash
+    /// with the Events of a Fragment in another domain, even for identical
+    /// Facts.
+    ///
+    /// `append` and the other methods that build Events take care of the
+    /// domain. Events built by hand must name their parents by `hash_of`,
+    /// not `Event::hash`. `write_to` and `write_compact` can't record the
+    /// domain, so they refuse Fragments with one.
+    ///
+    /// ```
+    /// use pender::fragment::Fragment;
+    ///
+    /// let mut ours = Fragment::new_with_domain(b"ours");
+    /// let mut theirs = Fragment::new_with_domain(b"theirs");
+    /// assert_ne!(ours.append(b"potato").unwrap(), theirs.append(b"potato").unwrap());
+    /// ```
+    pub fn new_with_domain(domain: &'a [u8]) -> Fragment<'a> {
+        Fragment::with_domain(domain)
     }
 
-    pub fn summarize (self, name: &'a str) -> Chain<'a> {
-        Chain::new(self, name)
+    /// An empty Fragment whose Events are all hashed under the Blake2
+    /// `key`, so that Fragments with different keys never share an Event
+    /// hash, and only holders of the key can make Events that fit.
+    ///
+    /// As with `new_with_domain`, Events built by hand must name their
+    /// parents by `hash_of`, and the binary formats refuse keyed
+    /// Fragments. The key isn't serialized either.
+    ///
+    /// # Panics
+    /// If `key` is longer than 64 bytes.
+    ///
+    /// ```
+    /// use pender::fragment::Fragment;
+    ///
+    /// let mut ours = Fragment::with_key(b"our secret".to_vec());
+    /// let mut theirs = Fragment::with_key(b"their secret".to_vec());
+    /// assert_ne!(ours.append(b"potato").unwrap(), theirs.append(b"potato").unwrap());
+    /// ```
+    pub fn with_key(key: Vec<u8>) -> Fragment<'a> {
+        assert!(key.len() <= 64, "Blake2 keys are at most 64 bytes, not {}", key.len());
+        Fragment { key: key, ..Default::default() }
     }
 }
 
-#[derive(Debug)]
-pub struct Chain<'a> {
-    fragment: Fragment<'a>,
-    summary: &'a str,
-    next: Option<Blake2>,
-}
+impl<'a, H: EventHash> Fragment<'a, H> {
+    /// Like `new_with_domain`, for Events identified by a hash other than
+    /// `Blake2`.
+    pub fn with_domain(domain: &'a [u8]) -> Fragment<'a, H> {
+        Fragment { domain: domain, ..Default::default() }
+    }
 
-impl<'a> Chain<'a> {
-    pub fn new(fragment: Fragment<'a>, summary: &'a str) -> Chain<'a> {
-        let head = fragment.head;
-        Chain {
-            fragment: fragment,
-            summary: summary,
-            next: head.map(|e| e.hash())
+    /// The domain folded into every Event's hash; empty for none.
+    pub fn domain(&self) -> &'a [u8] {
+        self.domain
+    }
+
+    /// The hash under which `event` is (or would be) stored in this
+    /// Fragment: `Event::hash_with` the Fragment's key and domain.
+    pub fn hash_of(&self, event: Event<H>) -> H {
+        event.hash_with(&self.key, self.domain)
+    }
+
+    /// An empty Fragment hashing Events the same way as this one.
+    pub(crate) fn empty_like(&self) -> Fragment<'a, H> {
+        Fragment { domain: self.domain, key: self.key.clone(), ..Default::default() }
+    }
+
+    /// `hash_of`, reusing the hash `event` has already computed if the
+    /// Fragment has no domain or key.
+    fn key_of(&self, event: &CachedEvent<'a, H>) -> H {
+        if self.domain.is_empty() && self.key.is_empty() {
+            event.hash()
+        } else {
+            self.hash_of(event.event())
         }
     }
 
-    pub fn next_event(&mut self) -> Link<'a> {
-        match self.next {
-            None => Link::Terminus(None),
-            Some(hash) => {
-                if let Some(event) = self.fragment.events.get(&hash) {
-                    self.next = event.parent();
-                    Link::Event(*event)
-                } else {
-                    Link::Terminus(Some(hash))
+    /// A Root holding `fact`, or a Node on top of `parent`, named by its
+    /// hash in this Fragment.
+    fn child_of(&self, fact: &'a [u8], parent: Option<Event<'a, H>>) -> Event<'a, H> {
+        match parent {
+            None => Event::Root { fact: fact },
+            Some(parent) => Event::Node { fact: fact, parent_hash: self.hash_of(parent) },
+        }
+    }
+
+    /// Append `fact` as a new Event on top of the head, returning the new
+    /// Event's hash. Fails only if the Fragment is sealed.
+    pub fn append(&mut self, fact: &'a [u8]) -> Result<H, AppendError<H>> {
+        self.append_at(fact, now())
+    }
+
+    /// Like `append`, with a UTF-8 Fact.
+    ///
+    /// ```
+    /// use pender::fragment::Fragment;
+    ///
+    /// let mut frag = Fragment::new();
+    /// frag.append_str("hello").unwrap();
+    /// assert_eq!(frag.head.unwrap().fact(), b"hello");
+    /// ```
+    pub fn append_str(&mut self, fact: &'a str) -> Result<H, AppendError<H>> {
+        self.append(fact.as_bytes())
+    }
+
+    /// Like `append`, recording `timestamp` instead of the current time.
+    pub fn append_at(&mut self, fact: &'a [u8], timestamp: u64) -> Result<H, AppendError<H>> {
+        let event = CachedEvent::new(self.child_of(fact, self.head));
+        self.append_cached_at(&event, timestamp)?;
+        Ok(self.key_of(&event))
+    }
+
+    /// Append each of `facts` in turn, each one the parent of the next,
+    /// leaving the head at the last. Returns the hashes of the new Events in
+    /// the same order.
+    pub fn append_all(&mut self, facts: &[&'a [u8]]) -> Result<Vec<H>, AppendError<H>> {
+        self.check_sealed()?;
+        let timestamp = now();
+        let mut parent_hash = self.head.map(|head| self.hash_of(head));
+        let mut hashes = Vec::with_capacity(facts.len());
+        for &fact in facts {
+            let event = match parent_hash {
+                None => Event::Root { fact: fact },
+                Some(parent_hash) => Event::Node { fact: fact, parent_hash: parent_hash },
+            };
+            let event = CachedEvent::new(event);
+            self.append_cached_at(&event, timestamp)?;
+            let hash = self.key_of(&event);
+            parent_hash = Some(hash);
+            hashes.push(hash);
+        }
+        Ok(hashes)
+    }
+
+    /// Make `event` the head, whether or not its parents are present.
+    ///
+    /// Appending an Event whose parent is missing leaves the Fragment with
+    /// a broken chain; use `try_append_event` unless the Event is known to
+    /// fit.
+    pub fn append_event (&mut self, event: Event<'a, H>) -> Result<(), AppendError<H>> { 
+        self.append_event_at(event, now())
+    }
+
+    /// Like `append_event`, but does nothing (not even moving the head) if
+    /// `event` is already stored. True if the Event was new.
+    pub fn append_event_dedup(&mut self, event: Event<'a, H>) -> Result<bool, AppendError<H>> {
+        let event = CachedEvent::new(event);
+        if self.contains(&self.key_of(&event)) {
+            return Ok(false);
+        }
+        self.append_cached(&event)?;
+        Ok(true)
+    }
+
+    /// Like `append`, but fails if the Fragment is sealed or `fact` is
+    /// larger than the limit set by `set_max_fact_size`.
+    pub fn try_append(&mut self, fact: &'a [u8]) -> Result<H, AppendError<H>> {
+        self.check_sealed()?;
+        self.check_fact_size(fact)?;
+        self.append(fact)
+    }
+
+    /// Like `append_event`, but first checks that the Fragment isn't
+    /// sealed, that every parent of `event` is stored in the Fragment (Root
+    /// Events have none) and that its Fact is within the limit set by
+    /// `set_max_fact_size`.
+    pub fn try_append_event(&mut self, event: Event<'a, H>) -> Result<(), AppendError<H>> {
+        self.check_sealed()?;
+        self.check_fact_size(event.fact())?;
+        if let Some(&missing) = event.parents().iter().find(|parent| !self.contains(parent)) {
+            return Err(AppendError::MissingParent(missing));
+        }
+        self.append_event(event)
+    }
+
+    /// Make `try_append` and `try_append_event` refuse Facts longer than
+    /// `bytes`. A limit of 0 (the default) accepts Facts of any size. The
+    /// unchecked `append` methods ignore the limit.
+    pub fn set_max_fact_size(&mut self, bytes: usize) {
+        self.max_fact_size = bytes;
+    }
+
+    /// Refuse any further changes to the Events, e.g. once the Fragment has
+    /// been summarized and published:
+    ///
+    /// - the `append` methods, `try_` or not, fail with
+    ///   `AppendError::Sealed` and leave the Fragment unchanged;
+    /// - `merge` and `graft` fail with their `Sealed` errors;
+    /// - `prune`, `gc` and `retain` remove nothing.
+    ///
+    /// `head` and `events` are public, so they can still be changed
+    /// directly, and `reindex` can still repair the keys. The seal is kept
+    /// by `clone` and with the `serde` feature, but `write_to` and
+    /// `write_compact` don't record it, so Fragments read back with
+    /// `read_from` or `read_compact` are never sealed.
+    pub fn seal(&mut self) {
+        self.sealed = true;
+    }
+
+    /// True once `seal` has been called.
+    pub fn is_sealed(&self) -> bool {
+        self.sealed
+    }
+
+    fn check_sealed(&self) -> Result<(), AppendError<H>> {
+        if self.sealed {
+            return Err(AppendError::Sealed);
+        }
+        Ok(())
+    }
+
+    fn check_fact_size(&self, fact: &[u8]) -> Result<(), AppendError<H>> {
+        if self.max_fact_size != 0 && fact.len() > self.max_fact_size {
+            return Err(AppendError::FactTooLarge { size: fact.len(), limit: self.max_fact_size });
+        }
+        Ok(())
+    }
+
+    /// Like `append_event`, recording `timestamp` instead of the current
+    /// time. Appending an Event that is already present keeps its original
+    /// timestamp.
+    pub fn append_event_at(&mut self, event: Event<'a, H>, timestamp: u64)
+        -> Result<(), AppendError<H>>
+    {
+        self.append_cached_at(&CachedEvent::new(event), timestamp)
+    }
+
+    /// Like `append_event`, reusing the hash `event` has already computed
+    /// (if any) rather than hashing it again.
+    pub fn append_cached(&mut self, event: &CachedEvent<'a, H>) -> Result<(), AppendError<H>> {
+        self.append_cached_at(event, now())
+    }
+
+    fn append_cached_at(&mut self, event: &CachedEvent<'a, H>, timestamp: u64)
+        -> Result<(), AppendError<H>>
+    {
+        self.insert_cached(event, timestamp)?;
+        self.head = Some(event.event());
+        Ok(())
+    }
+
+    /// Store `event` without moving any branch onto it.
+    fn insert_cached(&mut self, event: &CachedEvent<'a, H>, timestamp: u64)
+        -> Result<(), AppendError<H>>
+    {
+        self.check_sealed()?;
+        let hash = self.key_of(event);
+        self.events.insert(hash, event.event());
+        self.timestamps.entry(hash).or_insert(timestamp);
+        for hook in &mut self.hooks {
+            hook(&event.event(), &hash);
+        }
+        Ok(())
+    }
+
+    /// Register `hook` to be called with every Event appended from now on,
+    /// by any of the `append` methods, once it is stored, along with the
+    /// hash it is stored under. That is `hash_of` the Event, which differs
+    /// from `Event::hash` in a Fragment with a domain or key. Callbacks run
+    /// in the order they were registered, and aren't carried over by
+    /// `clone`. See `AppendHook` for why they must be `Send` and `Sync`.
+    ///
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    /// use pender::fragment::Fragment;
+    ///
+    /// let index = Arc::new(Mutex::new(Vec::new()));
+    /// let mut frag = Fragment::new_with_domain(b"indexed");
+    /// let recorder = index.clone();
+    /// frag.on_append(Box::new(move |_, &hash| recorder.lock().unwrap().push(hash)));
+    /// let hash = frag.append(b"potato").unwrap();
+    /// assert_eq!(*index.lock().unwrap(), vec![hash]);
+    /// ```
+    pub fn on_append(&mut self, hook: AppendHook<H>) {
+        self.hooks.push(hook);
+    }
+
+    /// Append `fact` on top of the tip of the named branch, or as a new
+    /// root if the branch doesn't exist yet, and make it the branch's tip.
+    /// Returns the new Event's hash.
+    ///
+    /// Appending to `DEFAULT_BRANCH` is the same as `append`; other
+    /// branches leave the head alone.
+    ///
+    /// ```
+    /// use pender::fragment::Fragment;
+    ///
+    /// let mut frag = Fragment::new();
+    /// let mine = frag.append_to("main", b"mine").unwrap();
+    /// let theirs = frag.append_to("theirs", b"theirs").unwrap();
+    /// assert_eq!(frag.head.unwrap().hash(), mine);
+    /// assert_eq!(frag.branch_head("theirs").unwrap().hash(), theirs);
+    /// ```
+    pub fn append_to(&mut self, branch: &str, fact: &'a [u8]) -> Result<H, AppendError<H>> {
+        if branch == DEFAULT_BRANCH {
+            return self.append(fact);
+        }
+        let tip = self.branch_head(branch).cloned();
+        let event = CachedEvent::new(self.child_of(fact, tip));
+        self.insert_cached(&event, now())?;
+        let hash = self.key_of(&event);
+        self.branches.insert(branch.to_string(), hash);
+        Ok(hash)
+    }
+
+    /// The tip of the named branch, or None if it has no Events (or its tip
+    /// is missing). The tip of `DEFAULT_BRANCH` is `head`.
+    pub fn branch_head(&self, name: &str) -> Option<&Event<'a, H>> {
+        if name == DEFAULT_BRANCH {
+            return self.head.as_ref();
+        }
+        self.branches.get(name).and_then(|tip| self.get(tip))
+    }
+
+    /// When the Event with this hash was appended, in milliseconds since the
+    /// Unix epoch, if it was appended to this Fragment (or one merged into
+    /// it).
+    pub fn timestamp(&self, hash: &H) -> Option<u64> {
+        self.timestamps.get(hash).cloned()
+    }
+
+    /// The Event stored under `hash`, if any.
+    pub fn get(&self, hash: &H) -> Option<&Event<'a, H>> {
+        self.events.get(hash)
+    }
+
+    /// True if an Event with this hash is stored in the Fragment.
+    pub fn contains(&self, hash: &H) -> bool {
+        self.events.contains_key(hash)
+    }
+
+    /// Every stored Event, in no particular order.
+    ///
+    /// Unlike walking a Chain (see `summarize`), this includes Events that
+    /// are not ancestors of the head, and does not go from head to root.
+    pub fn iter(&self) -> map::Values<'_, H, Event<'a, H>> {
+        self.events.values()
+    }
+
+    /// Walk from the Event stored under `from` towards its root, following
+    /// the same parent links as a Chain. Yields `from` itself first, and
+    /// stops at the first missing Event (immediately, if `from` is absent).
+    pub fn ancestors(&self, from: &H) -> Ancestors<'_, 'a, H> {
+        Ancestors { fragment: self, next: Some(*from) }
+    }
+
+    /// The head's chain in the opposite order to a Chain or `ancestors`:
+    /// from its root (or the first missing link) up to the head.
+    ///
+    /// Parent links only lead towards the root, so the chain is walked
+    /// once up front and held in memory, one Event per link: memory use is
+    /// O(n) in the length of the chain, as collecting a Chain would be.
+    /// Iterating then costs nothing more.
+    pub fn replay(&self) -> Replay<'a, H> {
+        let events = self.head
+            .map_or(Vec::new(), |head| self.ancestors(&self.hash_of(head)).cloned().collect());
+        Replay { events: events }
+    }
+
+    /// True if following parent links from `descendant` (through every
+    /// parent of a Merge, not just the first) reaches `ancestor`. An Event
+    /// is not its own ancestor; see `is_ancestor_or_self`.
+    pub fn is_ancestor_of(&self, ancestor: &H, descendant: &H) -> bool {
+        let mut seen = Set::new();
+        let mut pending: Vec<H> = self.get(descendant)
+            .map_or(Vec::new(), |event| event.parents().to_vec());
+        while let Some(hash) = pending.pop() {
+            if hash == *ancestor {
+                return true;
+            }
+            if seen.insert(hash) {
+                if let Some(event) = self.get(&hash) {
+                    pending.extend(event.parents());
                 }
             }
         }
+        false
     }
 
-    fn set_next(&mut self) {
-        // TODO: implement
+    /// True if `ancestor` and `descendant` are the same, or
+    /// `is_ancestor_of(ancestor, descendant)`.
+    pub fn is_ancestor_or_self(&self, ancestor: &H, descendant: &H) -> bool {
+        ancestor == descendant || self.is_ancestor_of(ancestor, descendant)
     }
-}
 
-#[derive(Debug, PartialEq)]
-pub enum Link<'a> {
-    Event(Event<'a>),
-    Terminus(Option<Blake2>),
+    /// Hash of the nearest Event that is `a` or one of its ancestors and
+    /// also `b` or one of its ancestors, or None if they share no history.
+    ///
+    /// If one of them descends from the other, the older one is returned.
+    /// Where several common ancestors exist (after Merges), the one fewest
+    /// parent links away from `b` wins.
+    pub fn common_ancestor(&self, a: &H, b: &H) -> Option<H> {
+        let a_lineage: Set<H> = self.lineage(a).into_iter().collect();
+        self.lineage(b).into_iter().find(|hash| a_lineage.contains(hash))
+    }
+
+    /// The Events from `to` down to `from`, both included, head first:
+    /// `ancestors(to)` up to and including `from`. Like `ancestors`, this
+    /// follows only the first parent of a Merge.
+    ///
+    /// ```
+    /// use pender::fragment::Fragment;
+    ///
+    /// let mut frag = Fragment::new();
+    /// let hashes = frag.append_all(&[b"one", b"two", b"three", b"four"]).unwrap();
+    /// let slice = frag.slice(&hashes[1], &hashes[2]).unwrap();
+    /// assert_eq!(slice.iter().map(|event| event.fact()).collect::<Vec<_>>(),
+    ///            vec![&b"three"[..], &b"two"[..]]);
+    /// ```
+    pub fn slice(&self, from: &H, to: &H) -> Result<Vec<Event<'a, H>>, SliceError<H>> {
+        if !self.contains(to) {
+            return Err(SliceError::NotFound(*to));
+        }
+        let mut slice = Vec::new();
+        for &event in self.ancestors(to) {
+            slice.push(event);
+            if self.hash_of(event) == *from {
+                return Ok(slice);
+            }
+        }
+        Err(SliceError::NotAncestor)
+    }
+
+    /// Map from the hash of every stored Event to the hashes of the Events
+    /// naming it as a parent, in no particular order. Childless Events map
+    /// to an empty Vec; parents that are named but not stored get an entry
+    /// too.
+    pub fn children_index(&self) -> Map<H, Vec<H>> {
+        let mut index: Map<H, Vec<H>> = map_with_capacity(self.events.len());
+        for (&hash, event) in &self.events {
+            index.entry(hash).or_default();
+            for &parent in event.parents() {
+                index.entry(parent).or_default().push(hash);
+            }
+        }
+        index
+    }
+
+    /// Hashes of the stored Events that are nobody's parent: the tips of
+    /// every branch, in no particular order.
+    pub fn leaves(&self) -> Vec<H> {
+        self.children_index().into_iter()
+            .filter(|(hash, children)| children.is_empty() && self.contains(hash))
+            .map(|(hash, _)| hash)
+            .collect()
+    }
+
+    /// Hashes of the stored Root Events, in no particular order. Each
+    /// begins an independent history.
+    pub fn roots(&self) -> Vec<H> {
+        self.events.iter()
+            .filter(|&(_, event)| event.is_root())
+            .map(|(&hash, _)| hash)
+            .collect()
+    }
+
+    /// True if the Fragment is a single path from one Root to the head:
+    /// exactly one Root and one leaf, which is the head, every Event with at
+    /// most one child, and no parent missing. An empty Fragment is linear.
+    pub fn is_linear(&self) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+        let children = self.children_index();
+        let head = self.head.map(|head| self.hash_of(head));
+        children.len() == self.events.len()
+            && children.values().all(|children| children.len() <= 1)
+            && self.roots().len() == 1
+            && self.leaves() == head.into_iter().collect::<Vec<H>>()
+    }
+
+    /// Number of separate histories in the Fragment: groups of stored
+    /// Events connected by parent links, followed in either direction. 0
+    /// for an empty Fragment, 1 for a single coherent log. Events are not
+    /// joined through a parent that is missing.
+    pub fn component_count(&self) -> usize {
+        let children = self.children_index();
+        let mut seen = set_with_capacity(self.events.len());
+        let mut count = 0;
+        for &start in self.events.keys() {
+            if !seen.insert(start) {
+                continue;
+            }
+            count += 1;
+            let mut pending = vec![start];
+            while let Some(hash) = pending.pop() {
+                let neighbours = self.events[&hash].parents().iter().chain(&children[&hash]);
+                for &next in neighbours {
+                    if self.contains(&next) && seen.insert(next) {
+                        pending.push(next);
+                    }
+                }
+            }
+        }
+        count
+    }
+
+    /// Hashes of every stored Event, ordered so that each comes after all
+    /// of its parents that are stored. Events whose parents are all missing
+    /// come first, as roots do. Ties are broken by hash, so the order is
+    /// the same every time.
+    ///
+    /// Fails if the parent links form a cycle, which can only happen if
+    /// Events were stored under keys other than their own hashes.
+    pub fn topo_sort(&self) -> Result<Vec<H>, TopoSortError<H>> {
+        let children = self.children_index();
+        let mut waiting_on: Map<H, usize> = self.events.iter()
+            .map(|(&hash, event)| {
+                let present = event.parents().iter().filter(|parent| self.contains(parent));
+                (hash, present.count())
+            })
+            .collect();
+
+        let mut ready: Vec<H> = waiting_on.iter()
+            .filter(|&(_, &count)| count == 0)
+            .map(|(&hash, _)| hash)
+            .collect();
+        ready.sort();
+        let mut ready: VecDeque<H> = ready.into_iter().collect();
+
+        let mut order = Vec::with_capacity(self.events.len());
+        while let Some(hash) = ready.pop_front() {
+            order.push(hash);
+            let mut unblocked = Vec::new();
+            for child in &children[&hash] {
+                if let Some(count) = waiting_on.get_mut(child) {
+                    *count -= 1;
+                    if *count == 0 {
+                        unblocked.push(*child);
+                    }
+                }
+            }
+            unblocked.sort();
+            ready.extend(unblocked);
+        }
+
+        if order.len() < self.events.len() {
+            let mut stuck: Vec<H> = waiting_on.into_iter()
+                .filter(|&(_, count)| count > 0)
+                .map(|(hash, _)| hash)
+                .collect();
+            stuck.sort();
+            return Err(TopoSortError::Cycle(stuck));
+        }
+        Ok(order)
+    }
+
+    /// Total number of Events stored, on the head's chain or not.
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// True if the Fragment holds no Events.
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Length of the chain from the head back to its root: 0 for an empty
+    /// Fragment, 1 for a lone root. If an Event on the way is missing, the
+    /// count stops there.
+    pub fn depth(&self) -> usize {
+        self.head.map_or(0, |head| self.depth_from(self.hash_of(head)))
+    }
+
+    /// Walks the chain from the head back to its root (see `Chain`). The
+    /// Fragment is only borrowed, so it can be appended to again once the
+    /// Chain is dropped.
+    pub fn summarize<'f>(&'f self, name: &'f str) -> Chain<'f, 'a, H> {
+        Chain::new(self, name)
+    }
+
+    /// Copies of the Facts of up to `n` Events from the head back toward
+    /// the root, newest first. Being owned, they can outlive the Fragment.
+    pub fn tip_facts(&self, n: usize) -> Vec<Vec<u8>> {
+        self.summarize("tip").facts().take(n).map(|fact| fact.to_vec()).collect()
+    }
+
+    /// A Summary named `name` holding `blob`, for the current head; None if
+    /// the Fragment is empty.
+    ///
+    /// ```
+    /// use pender::fragment::Fragment;
+    ///
+    /// let mut frag = Fragment::new();
+    /// let head = frag.append(b"one").unwrap();
+    /// let summary = frag.checkpoint("count", b"1").unwrap();
+    /// assert_eq!(summary.event(), head);
+    /// ```
+    pub fn checkpoint(&self, name: &'a str, blob: &'a [u8]) -> Option<Summary<'a, H>> {
+        self.head.map(|head| Summary::new(name, blob, self.hash_of(head)))
+    }
+
+    /// Keep `summary` with the Fragment. Summaries of ancestors stay valid
+    /// as more Events are appended.
+    pub fn add_summary(&mut self, summary: Summary<'a, H>) {
+        self.summaries.push(summary);
+    }
+
+    /// The Summaries attached with `add_summary`, oldest first.
+    pub fn summaries(&self) -> &[Summary<'a, H>] {
+        &self.summaries
+    }
+
+    /// The attached Summary of the deepest Event, i.e. the most recent on
+    /// its chain; the one added last if several are equally deep. None if
+    /// there are no Summaries.
+    ///
+    /// ```
+    /// use pender::fragment::Fragment;
+    ///
+    /// let mut frag = Fragment::new();
+    /// frag.append(b"one").unwrap();
+    /// let first = frag.checkpoint("count", b"1").unwrap();
+    /// frag.add_summary(first);
+    /// frag.append(b"two").unwrap();
+    /// let second = frag.checkpoint("count", b"2").unwrap();
+    /// frag.add_summary(second);
+    /// assert_eq!(frag.latest_summary(), Some(&second));
+    /// ```
+    pub fn latest_summary(&self) -> Option<&Summary<'a, H>> {
+        self.summaries.iter().max_by_key(|summary| self.depth_from(summary.event()))
+    }
+
+    /// Add every Event and Summary in `other` to this Fragment.
+    ///
+    /// Events are keyed by hash, so ones already present are not
+    /// duplicated. The head becomes whichever of the two heads is chosen by
+    /// `deeper_head`; if either Fragment is empty, the other's head is kept.
+    /// Other branches in both Fragments are resolved the same way.
+    ///
+    /// Fails without changing anything if this Fragment is sealed, or if
+    /// `other` hashes its Events under a different domain or key, since
+    /// they wouldn't fit this Fragment.
+    pub fn merge(&mut self, other: Fragment<'a, H>) -> Result<(), MergeError> {
+        if self.sealed {
+            return Err(MergeError::Sealed);
+        }
+        if other.domain != self.domain {
+            return Err(MergeError::DomainMismatch);
+        }
+        if other.key != self.key {
+            return Err(MergeError::KeyMismatch);
+        }
+        self.events.extend(other.events);
+        for (hash, timestamp) in other.timestamps {
+            self.timestamps.entry(hash).or_insert(timestamp);
+        }
+        self.head = match (self.head, other.head) {
+            (Some(mine), Some(theirs)) => Some(self.deeper_head(mine, theirs)),
+            (mine, theirs) => mine.or(theirs),
+        };
+        for (name, theirs) in other.branches {
+            let tip = match self.branches.get(&name) {
+                Some(&mine) if self.depth_from(mine) >= self.depth_from(theirs) => mine,
+                _ => theirs,
+            };
+            self.branches.insert(name, tip);
+        }
+        for summary in other.summaries {
+            if !self.summaries.contains(&summary) {
+                self.summaries.push(summary);
+            }
+        }
+        Ok(())
+    }
+
+    /// Copy `from` and all of its ancestors, through every parent of every
+    /// Merge, from `source` into this Fragment under the keys they have in
+    /// `source`, along with their timestamps. Events already present are
+    /// skipped. The head is left alone; set it to `source.get(from)` to
+    /// adopt the grafted chain. Returns how many Events were copied.
+    ///
+    /// Fails without copying anything if this Fragment is sealed, or if
+    /// `from` or any of its ancestors is not stored in `source`.
+    ///
+    /// ```
+    /// use pender::fragment::Fragment;
+    ///
+    /// let mut source = Fragment::new();
+    /// let hashes = source.append_all(&[b"one", b"two", b"three"]).unwrap();
+    ///
+    /// let mut frag = Fragment::new();
+    /// assert_eq!(frag.graft(&source, &hashes[1]), Ok(2));
+    /// assert!(frag.contains(&hashes[0]) && !frag.contains(&hashes[2]));
+    /// ```
+    pub fn graft(&mut self, source: &Fragment<'a, H>, from: &H) -> Result<usize, GraftError<H>> {
+        if self.sealed {
+            return Err(GraftError::Sealed);
+        }
+        let mut seen = Set::new();
+        let mut grafted = Vec::new();
+        let mut pending = vec![*from];
+        while let Some(hash) = pending.pop() {
+            if !seen.insert(hash) {
+                continue;
+            }
+            let event = source.get(&hash).ok_or(GraftError::NotFound(hash))?;
+            if !self.contains(&hash) {
+                grafted.push((hash, *event));
+            }
+            pending.extend(event.parents());
+        }
+
+        for &(hash, event) in &grafted {
+            self.events.insert(hash, event);
+            if let Some(timestamp) = source.timestamp(&hash) {
+                self.timestamps.entry(hash).or_insert(timestamp);
+            }
+        }
+        Ok(grafted.len())
+    }
+
+    /// Which Events are stored in only one of the two Fragments, e.g. to
+    /// work out what to send a peer and what to ask it for.
+    pub fn diff(&self, other: &Fragment<H>) -> FragmentDiff<H> {
+        let mut only_self: Vec<H> = self.events.keys()
+            .filter(|hash| !other.contains(hash))
+            .cloned()
+            .collect();
+        let mut only_other: Vec<H> = other.events.keys()
+            .filter(|hash| !self.contains(hash))
+            .cloned()
+            .collect();
+        only_self.sort();
+        only_other.sort();
+        FragmentDiff {
+            common: self.len() - only_self.len(),
+            only_self: only_self,
+            only_other: only_other,
+        }
+    }
+
+    /// Whichever of `a` and `b` has the longer chain of ancestors in this
+    /// Fragment (counting the event itself), or `a` if they tie.
+    ///
+    /// When one event descends from the other, the descendant is always the
+    /// deeper of the two, so heads on the same chain resolve to the newer one.
+    pub fn deeper_head(&self, a: Event<'a, H>, b: Event<'a, H>) -> Event<'a, H> {
+        if self.depth_from(self.hash_of(b)) > self.depth_from(self.hash_of(a)) { b } else { a }
+    }
+
+    /// Check that every Event is stored under its own hash and that every
+    /// parent of every Node or Merge is present.
+    ///
+    /// A Fragment that deliberately holds only part of a history (e.g. one
+    /// that is still syncing) fails with `MissingParent`; see `orphans`.
+    #[allow(clippy::result_large_err)]
+    pub fn verify(&self) -> Result<(), IntegrityError<H>> {
+        self.events.iter().try_for_each(|(stored, event)| self.verify_event(event, stored))
+    }
+
+    /// The check `verify` makes of each Event, for a single one (e.g. one
+    /// just received, before appending it): that `event` hashes to
+    /// `expected_hash`, and that all of its parents are stored.
+    #[allow(clippy::result_large_err)]
+    pub fn verify_event(&self, event: &Event<H>, expected_hash: &H)
+        -> Result<(), IntegrityError<H>>
+    {
+        let computed = self.hash_of(*event);
+        if computed != *expected_hash {
+            return Err(IntegrityError::HashMismatch { stored: *expected_hash, computed: computed });
+        }
+        match event.parents().iter().find(|parent| !self.contains(parent)) {
+            Some(&missing) => Err(IntegrityError::MissingParent(missing)),
+            None => Ok(()),
+        }
+    }
+
+    /// Re-key every Event by its freshly computed hash, repairing a
+    /// Fragment that `verify` rejects with `HashMismatch`. Timestamps and
+    /// branch tips move to the new keys; the head is an Event, so it
+    /// resolves to its new key by itself. Returns how many keys changed.
+    pub fn reindex(&mut self) -> usize {
+        let mut changed = 0;
+        let mut moved = Map::new();
+        let mut events = map_with_capacity(self.events.len());
+        for (stored, event) in mem::take(&mut self.events) {
+            let computed = event.hash_with(&self.key, self.domain);
+            if computed != stored {
+                changed += 1;
+                moved.insert(stored, computed);
+            }
+            events.insert(computed, event);
+        }
+        self.events = events;
+
+        for (stored, computed) in &moved {
+            if let Some(timestamp) = self.timestamps.remove(stored) {
+                self.timestamps.entry(*computed).or_insert(timestamp);
+            }
+        }
+        for tip in self.branches.values_mut() {
+            if let Some(computed) = moved.get(tip) {
+                *tip = *computed;
+            }
+        }
+        changed
+    }
+
+    /// Hashes of the Events with a parent that is not in the Fragment, in no
+    /// particular order. Their parents are what's needed to complete it.
+    pub fn orphans(&self) -> Vec<H> {
+        self.events.iter()
+            .filter(|&(_, event)| event.parents().iter().any(|parent| !self.contains(parent)))
+            .map(|(&hash, _)| hash)
+            .collect()
+    }
+
+    /// Counts describing the Fragment's shape and size, for monitoring. The
+    /// counts agree with `len`, `depth`, `roots`, `leaves` and `orphans`.
+    pub fn stats(&self) -> FragmentStats {
+        let children = self.children_index();
+        let mut stats = FragmentStats {
+            total_events: self.len(),
+            depth: self.depth(),
+            ..Default::default()
+        };
+        for (hash, event) in &self.events {
+            if event.is_root() {
+                stats.root_count += 1;
+            }
+            if children[hash].is_empty() {
+                stats.leaf_count += 1;
+            }
+            if event.parents().iter().any(|parent| !self.contains(parent)) {
+                stats.orphan_count += 1;
+            }
+            stats.total_fact_bytes += event.fact().len();
+        }
+        stats
+    }
+
+    /// Sum of `Event::byte_len` over every stored Event, for estimating
+    /// how much a Fragment holds.
+    pub fn total_bytes(&self) -> usize {
+        self.events.values().map(Event::byte_len).sum()
+    }
+
+    /// The hash of the one stored Event whose hex encoding starts with
+    /// `prefix` (in either case), like git's short hashes.
+    ///
+    /// # Example
+    /// ```
+    /// use pender::fragment::Fragment;
+    ///
+    /// let mut frag = Fragment::new();
+    /// let hash = frag.append(b"potato").unwrap();
+    /// assert_eq!(frag.resolve_prefix(&hash.to_hex()[..8]), Ok(hash));
+    /// ```
+    pub fn resolve_prefix(&self, prefix: &str) -> Result<H, ResolveError<H>> {
+        let prefix = prefix.to_lowercase();
+        let mut matches: Vec<H> = self.events.keys()
+            .filter(|hash| hash.to_hex().starts_with(&prefix))
+            .cloned()
+            .collect();
+        match matches.len() {
+            0 => Err(ResolveError::NotFound),
+            1 => Ok(matches[0]),
+            _ => {
+                matches.sort();
+                Err(ResolveError::Ambiguous(matches))
+            },
+        }
+    }
+
+    /// A Bloom filter of `bits` bits holding the hash of every stored
+    /// Event, setting `hashes` bits per Event. A peer can check it to skip
+    /// sending Events this Fragment probably has.
+    pub fn bloom(&self, bits: usize, hashes: usize) -> BloomFilter {
+        let mut bloom = BloomFilter::new(bits, hashes);
+        for hash in self.events.keys() {
+            bloom.insert(hash);
+        }
+        bloom
+    }
+
+    /// The stored Events among `wanted`, in the same order, skipping any
+    /// that aren't stored. Serves a peer's `sync::WantList`.
+    pub fn collect_events(&self, wanted: &[H]) -> Vec<Event<'a, H>> {
+        wanted.iter()
+            .filter_map(|hash| self.get(hash))
+            .cloned()
+            .collect()
+    }
+
+    /// The Events from the head down to and including `target`, following
+    /// the same parent links as a Chain, or None if `target` is not on the
+    /// head's chain.
+    ///
+    /// Anyone trusting only the head's hash can check the proof by
+    /// rehashing each Event in turn (see `verify_inclusion`, or
+    /// `verify_inclusion_with` for a Fragment with a domain or key).
+    pub fn inclusion_proof(&self, target: &H) -> Option<Vec<Event<'a, H>>> {
+        let head = self.head?;
+        let mut proof = Vec::new();
+        for event in self.ancestors(&self.hash_of(head)) {
+            proof.push(*event);
+            if self.hash_of(*event) == *target {
+                return Some(proof);
+            }
+        }
+        None
+    }
+
+    /// Remove the ancestors of `keep_from` on the head's chain, keeping
+    /// everything from the head down to and including `keep_from`. Returns
+    /// the number of Events removed.
+    ///
+    /// Does nothing, returning 0, if the Fragment is sealed or `keep_from`
+    /// is not on the chain from the head. Events off that chain are never
+    /// removed, even if pruning leaves them orphaned. Summaries of removed
+    /// Events are dropped.
+    pub fn prune(&mut self, keep_from: &H) -> usize {
+        if self.sealed {
+            return 0;
+        }
+        let kept = self.head.and_then(|head| {
+            self.ancestors(&self.hash_of(head)).find(|event| self.hash_of(**event) == *keep_from)
+        });
+        let mut next = match kept {
+            Some(event) => event.parent(),
+            None => return 0,
+        };
+
+        let mut removed = Set::new();
+        while let Some(hash) = next {
+            match self.events.remove(&hash) {
+                Some(event) => {
+                    self.timestamps.remove(&hash);
+                    removed.insert(hash);
+                    next = event.parent();
+                },
+                None => break,
+            }
+        }
+        self.summaries.retain(|summary| !removed.contains(&summary.event()));
+        removed.len()
+    }
+
+    /// Remove every Event that can't be reached by following parent links
+    /// (through every parent of a Merge) from the head or the tip of any
+    /// other branch. Returns the number of Events removed.
+    ///
+    /// Summaries of removed Events are dropped. A Fragment with no head and
+    /// no branches keeps nothing. A sealed Fragment keeps everything.
+    pub fn gc(&mut self) -> usize {
+        if self.sealed {
+            return 0;
+        }
+        let tips: Vec<H> = self.head.map(|head| self.hash_of(head)).into_iter()
+            .chain(self.branches.values().cloned())
+            .collect();
+        let reachable: Set<H> = tips.iter()
+            .flat_map(|tip| self.lineage(tip))
+            .collect();
+
+        let before = self.events.len();
+        self.events.retain(|hash, _| reachable.contains(hash));
+        self.timestamps.retain(|hash, _| reachable.contains(hash));
+        self.summaries.retain(|summary| reachable.contains(&summary.event()));
+        before - self.events.len()
+    }
+
+    /// Remove the Events for which `pred` is false, but only those whose
+    /// descendants in the Fragment are all being removed too, so that no
+    /// Event is left orphaned. Returns the number of Events removed.
+    ///
+    /// Removing an Event from the middle of a chain does nothing unless
+    /// the Events after it are removed as well. A head or branch tip that
+    /// is removed moves back to its parent, or goes if that is removed too.
+    /// Summaries of removed Events are dropped. A sealed Fragment keeps
+    /// every Event.
+    ///
+    /// ```
+    /// use pender::fragment::Fragment;
+    ///
+    /// let mut frag = Fragment::new();
+    /// frag.append_all(&[b"one", b"two", b"three"]).unwrap();
+    ///
+    /// // "two" still has a descendant, so it stays.
+    /// assert_eq!(frag.retain(|event| event.fact() != b"two"), 0);
+    ///
+    /// // Removing "three" along with it works, and the head moves back.
+    /// assert_eq!(frag.retain(|event| event.fact() == b"one"), 2);
+    /// assert_eq!(frag.head.unwrap().fact(), b"one");
+    /// ```
+    pub fn retain<F>(&mut self, pred: F) -> usize
+        where F: Fn(&Event<'a, H>) -> bool
+    {
+        if self.sealed {
+            return 0;
+        }
+        let failing: Set<H> = self.events.iter()
+            .filter(|&(_, event)| !pred(event))
+            .map(|(&hash, _)| hash)
+            .collect();
+
+        // Remove from the leaves inward: a failing Event goes once every
+        // one of its children has gone.
+        let mut children_left: Map<H, usize> = self.children_index().into_iter()
+            .filter(|(hash, _)| failing.contains(hash))
+            .map(|(hash, children)| (hash, children.len()))
+            .collect();
+        let mut ready: Vec<H> = children_left.iter()
+            .filter(|&(_, &count)| count == 0)
+            .map(|(&hash, _)| hash)
+            .collect();
+        let mut removed = Set::new();
+        while let Some(hash) = ready.pop() {
+            removed.insert(hash);
+            for parent in self.events[&hash].parents() {
+                if let Some(count) = children_left.get_mut(parent) {
+                    *count -= 1;
+                    if *count == 0 {
+                        ready.push(*parent);
+                    }
+                }
+            }
+        }
+
+        if let Some(head) = self.head.map(|head| self.hash_of(head)) {
+            if removed.contains(&head) {
+                self.head = self.surviving(head, &removed).map(|hash| self.events[&hash]);
+            }
+        }
+        for (name, tip) in mem::take(&mut self.branches) {
+            if let Some(tip) = self.surviving(tip, &removed) {
+                self.branches.insert(name, tip);
+            }
+        }
+        self.events.retain(|hash, _| !removed.contains(hash));
+        self.timestamps.retain(|hash, _| !removed.contains(hash));
+        self.summaries.retain(|summary| !removed.contains(&summary.event()));
+        removed.len()
+    }
+
+    /// `hash` if it isn't in `removed`, otherwise its nearest stored
+    /// ancestor along first parents that isn't; None if there is none.
+    fn surviving(&self, hash: H, removed: &Set<H>) -> Option<H> {
+        let mut hash = hash;
+        while removed.contains(&hash) {
+            hash = self.events[&hash].parent().filter(|parent| self.contains(parent))?;
+        }
+        Some(hash)
+    }
+
+    /// A Graphviz `digraph` of the stored Events, for debugging. Each Event
+    /// is a node labelled with the first eight hex digits of its hash and
+    /// the start of its Fact, with an edge to each of its parents that is
+    /// stored. Roots are drawn as grey boxes.
+    pub fn to_dot(&self) -> String {
+        let mut hashes: Vec<&H> = self.events.keys().collect();
+        hashes.sort();
+
+        let mut dot = String::from("digraph fragment {\n");
+        for hash in &hashes {
+            let event = &self.events[*hash];
+            let hex = hash.to_hex();
+            let preview: String = String::from_utf8_lossy(event.fact()).chars().take(20).collect();
+            let preview = preview.replace('\\', "\\\\").replace('"', "\\\"");
+            let label = format!("{}\\n{}", hash.short_hex(8), preview);
+            let style = if event.is_root() {
+                ", shape=box, style=filled, fillcolor=lightgrey"
+            } else {
+                ""
+            };
+            dot.push_str(&format!("    \"{}\" [label=\"{}\"{}];\n", hex, label, style));
+        }
+        for hash in &hashes {
+            let event = &self.events[*hash];
+            for parent in event.parents().iter().filter(|parent| self.contains(parent)) {
+                dot.push_str(&format!("    \"{}\" -> \"{}\";\n", hash.to_hex(), parent.to_hex()));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// A single hash of the whole set of stored Events, or None if there
+    /// are none: the hash of all the Event hashes concatenated in ascending
+    /// order. Fragments storing the same Events share a fingerprint however
+    /// they were built, and storing any other Event changes it. Unlike
+    /// hashing `canonical_bytes`, the head is not covered, and no Fact is
+    /// rehashed.
+    pub fn fingerprint(&self) -> Option<H> {
+        if self.events.is_empty() {
+            return None;
+        }
+        let mut hashes: Vec<&H> = self.events.keys().collect();
+        hashes.sort();
+        let parts: Vec<&[u8]> = hashes.iter().map(|hash| hash.as_ref()).collect();
+        Some(H::hash_parts(&parts))
+    }
+
+    /// Every stored hash in `topo_sort` order, or simply sorted should the
+    /// parent links form a cycle.
+    #[cfg(feature = "std")]
+    fn canonical_order(&self) -> Vec<H> {
+        self.topo_sort().unwrap_or_else(|_| {
+            let mut order: Vec<H> = self.events.keys().cloned().collect();
+            order.sort();
+            order
+        })
+    }
+
+    /// `from` and every stored Event reachable through any of its parents,
+    /// in breadth-first order.
+    fn lineage(&self, from: &H) -> Vec<H> {
+        let mut seen = Set::new();
+        let mut lineage = Vec::new();
+        let mut pending = VecDeque::new();
+        pending.push_back(*from);
+        while let Some(hash) = pending.pop_front() {
+            if let Some(event) = self.get(&hash) {
+                if seen.insert(hash) {
+                    lineage.push(hash);
+                    pending.extend(event.parents());
+                }
+            }
+        }
+        lineage
+    }
+
+    /// Number of events reached by following parent links from `hash`
+    /// (inclusive), stopping at a root or at the first missing event.
+    fn depth_from(&self, hash: H) -> usize {
+        self.ancestors(&hash).count()
+    }
+}
+
+/// The on-disk format stores 64-byte hashes, so only `Blake2` Fragments can
+/// be written and read.
+#[cfg(feature = "std")]
+impl<'a> Fragment<'a> {
+    /// Write the Fragment to `w`.
+    ///
+    /// The format is the magic bytes `PNDR`, the version byte 3, the
+    /// number of Events as a big-endian u64, a byte that is 1 if there is a
+    /// head and 0 otherwise, the head's hash (if any), and then each Event
+    /// as encoded by `Event::to_bytes`, in no particular order.
+    ///
+    /// Version 1 files, written before Events were hashed as they are now
+    /// (see `Event::hash`), are laid out the same way; their head no longer
+    /// matches any Event, so `read_from` refuses them as unsupported.
+    ///
+    /// The format has no room for a domain or key, and `read_from` keys
+    /// Events by `Event::hash`, so a Fragment with either couldn't be read
+    /// back: writing one fails with `io::ErrorKind::InvalidInput`, without
+    /// writing anything.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.check_writable()?;
+        self.write_events(w, self.events.values())
+    }
+
+    /// Fail if the Fragment has a domain or key, which the binary formats
+    /// can't record.
+    fn check_writable(&self) -> io::Result<()> {
+        if !self.domain.is_empty() || !self.key.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "binary formats can't record a domain or key"));
+        }
+        Ok(())
+    }
+
+    /// The Fragment in `write_to`'s format, with the Events in topological
+    /// order (see `topo_sort`), so that equal Fragments always give the
+    /// same bytes however they were built. `read_from` reads it back,
+    /// unless the Fragment has a domain or key (see `write_to`); the bytes
+    /// still serve as a fingerprint then.
+    ///
+    /// ```
+    /// use pender::fragment::Fragment;
+    /// use pender::hash::Blake2;
+    ///
+    /// let mut frag = Fragment::new();
+    /// frag.append(b"potato").unwrap();
+    /// let fingerprint = Blake2::new(&frag.canonical_bytes());
+    /// assert_eq!(fingerprint, Blake2::new(&frag.clone().canonical_bytes()));
+    /// ```
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        let order = self.canonical_order();
+        let mut buf = Vec::new();
+        self.write_events(&mut buf, order.iter().map(|hash| &self.events[hash]))
+            .expect("writing to a Vec can't fail");
+        buf
+    }
+
+    /// Write the `write_to` header, then `events`.
+    fn write_events<'f, W, I>(&self, w: &mut W, events: I) -> io::Result<()>
+        where W: Write, I: Iterator<Item = &'f Event<'a>>, 'a: 'f
+    {
+        w.write_all(MAGIC)?;
+        w.write_all(&[VERSION])?;
+        w.write_all(&(self.events.len() as u64).to_be_bytes())?;
+        match self.head {
+            None => w.write_all(&[0])?,
+            Some(head) => {
+                w.write_all(&[1])?;
+                w.write_all(&self.hash_of(head).bytes)?;
+            },
+        }
+        for event in events {
+            w.write_all(&event.to_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Read a Fragment written by `write_to`.
+    ///
+    /// Events are keyed by their recomputed hashes, and the head must be
+    /// among them. Since the Facts can't borrow from the reader, the result
+    /// owns them; use `OwnedFragment::as_fragment` to work with it.
+    ///
+    /// Malformed input gives an error rather than a panic, and declared
+    /// counts and lengths are checked against the input before anything is
+    /// allocated for them, so untrusted input is safe to read.
+    pub fn read_from<R: Read>(r: &mut R) -> Result<OwnedFragment, LoadError> {
+        let mut buf = Vec::new();
+        r.read_to_end(&mut buf)?;
+
+        let (count, head, mut pos) = Fragment::read_header(&buf)?;
+        let mut events = map_with_capacity(count as usize);
+        for _ in 0..count {
+            let (event, used) = event::decode(&buf[pos..])?;
+            pos += used;
+            events.insert(event.hash(), event);
+        }
+        if pos != buf.len() {
+            return Err(LoadError::Decode(DecodeError::TrailingBytes(buf.len() - pos)));
+        }
+
+        if let Some(head) = head {
+            if !events.contains_key(&head) {
+                return Err(LoadError::MissingHead(head));
+            }
+        }
+        Ok(OwnedFragment { head: head, events: events })
+    }
+
+    /// Map the file at `path`, written by `write_to`, into memory, for
+    /// reading the Fragment without copying its Facts (see
+    /// `MappedFragment`).
+    ///
+    /// # Safety
+    /// As for `MappedFragment::open`: the file must not be changed while it
+    /// is mapped.
+    #[cfg(feature = "mmap")]
+    pub unsafe fn from_mmap(path: &Path) -> io::Result<MappedFragment> {
+        MappedFragment::open(path)
+    }
+
+    /// Check the header of `write_to`'s format at the front of `buf`,
+    /// returning the declared number of Events (already checked against
+    /// the length of `buf`), the head's hash if any, and where the Events
+    /// start.
+    pub(crate) fn read_header(buf: &[u8])
+        -> Result<(u64, Option<Blake2>, usize), LoadError>
+    {
+        if buf.len() < HEADER_LEN {
+            return Err(LoadError::Decode(DecodeError::Truncated));
+        }
+        if &buf[..4] != MAGIC {
+            return Err(LoadError::BadMagic);
+        }
+        if buf[4] != VERSION {
+            return Err(LoadError::UnsupportedVersion(buf[4]));
+        }
+        let mut count = [0u8; 8];
+        count.copy_from_slice(&buf[5..13]);
+        let count = u64::from_be_bytes(count);
+
+        let mut pos = 14;
+        let head = match buf[13] {
+            0 => None,
+            _ => {
+                if buf.len() < pos + 64 {
+                    return Err(LoadError::Decode(DecodeError::Truncated));
+                }
+                let mut bytes = [0u8; 64];
+                bytes.copy_from_slice(&buf[pos..pos + 64]);
+                pos += 64;
+                Some(Blake2 { bytes: bytes })
+            },
+        };
+
+        // Check the declared count before trusting it to size anything.
+        if count > ((buf.len() - pos) / event::MIN_ENCODED_LEN) as u64 {
+            return Err(LoadError::Decode(DecodeError::Truncated));
+        }
+        Ok((count, head, pos))
+    }
+
+    /// Write the Fragment to `w` in a format that is usually much smaller
+    /// than `write_to`'s, since parents are rarely written out in full.
+    ///
+    /// The header is the magic bytes `PNDR`, the version byte 4, and the
+    /// number of Events as a big-endian u64, followed by a byte that is 1
+    /// if there is a head and 0 otherwise. The Events follow in topological
+    /// order (see `topo_sort`), each encoded like `Event::to_bytes` except
+    /// that each parent is a single byte: its distance back through the
+    /// Events already written, or 0 followed by its full hash if it is not
+    /// among the last 255 of them. Last comes the head (if any) as a
+    /// big-endian u64 index into the Events.
+    ///
+    /// Fails without writing anything if the head is not a stored Event,
+    /// or, as with `write_to`, if the Fragment has a domain or key. Files
+    /// from before Events were hashed as they are now have the
+    /// version byte 2, which `read_compact` refuses.
+    pub fn write_compact<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.check_writable()?;
+        let order = self.canonical_order();
+
+        let mut buf = Vec::new();
+        buf.extend_from_slice(MAGIC);
+        buf.push(COMPACT_VERSION);
+        buf.extend_from_slice(&(order.len() as u64).to_be_bytes());
+        buf.push(self.head.is_some() as u8);
+
+        let mut written = map_with_capacity(order.len());
+        for (index, hash) in order.iter().enumerate() {
+            let event = self.events[hash];
+            match event {
+                Event::Root { .. } => buf.push(event::ROOT_TAG),
+                Event::Node { .. } => buf.push(event::NODE_TAG),
+                Event::Merge { parents, .. } => {
+                    buf.push(event::MERGE_TAG);
+                    buf.extend_from_slice(&(parents.len() as u64).to_be_bytes());
+                },
+            }
+            for parent in event.parents() {
+                match written.get(parent).map(|&at| index - at) {
+                    Some(distance) if distance <= COMPACT_WINDOW => buf.push(distance as u8),
+                    _ => {
+                        buf.push(0);
+                        buf.extend_from_slice(&parent.bytes);
+                    },
+                }
+            }
+            buf.extend_from_slice(&(event.fact().len() as u64).to_be_bytes());
+            buf.extend_from_slice(event.fact());
+            written.insert(*hash, index);
+        }
+
+        if let Some(head) = self.head {
+            let index = written.get(&self.hash_of(head)).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "head is not a stored Event")
+            })?;
+            buf.extend_from_slice(&(*index as u64).to_be_bytes());
+        }
+        w.write_all(&buf)
+    }
+
+    /// Read a Fragment written by `write_compact`. Like `read_from`, the
+    /// result owns its Facts.
+    pub fn read_compact<R: Read>(r: &mut R) -> Result<OwnedFragment, LoadError> {
+        let mut buf = Vec::new();
+        r.read_to_end(&mut buf)?;
+
+        if buf.len() < HEADER_LEN {
+            return Err(LoadError::Decode(DecodeError::Truncated));
+        }
+        if &buf[..4] != MAGIC {
+            return Err(LoadError::BadMagic);
+        }
+        if buf[4] != COMPACT_VERSION {
+            return Err(LoadError::UnsupportedVersion(buf[4]));
+        }
+        let mut reader = event::Reader::new(&buf[5..]);
+        let count = reader.take_len(event::MIN_ENCODED_LEN)?;
+        let has_head = reader.take(1)?[0] != 0;
+
+        let mut order: Vec<Blake2> = Vec::with_capacity(count);
+        let mut events = map_with_capacity(count);
+        for _ in 0..count {
+            let tag = reader.take(1)?[0];
+            let parent_count = match tag {
+                event::ROOT_TAG => 0,
+                event::NODE_TAG => 1,
+                event::MERGE_TAG => reader.take_len(1)?,
+                _ => return Err(LoadError::Decode(DecodeError::BadTag(tag))),
+            };
+            let mut parents = Vec::with_capacity(parent_count);
+            for _ in 0..parent_count {
+                let parent = match reader.take(1)?[0] {
+                    0 => reader.take_hash()?,
+                    distance => *order.len().checked_sub(distance as usize)
+                        .and_then(|at| order.get(at))
+                        .ok_or(LoadError::BadReference(distance as u64))?,
+                };
+                parents.push(parent);
+            }
+            let len = reader.take_len(1)?;
+            let fact = reader.take(len)?.to_vec();
+
+            let event = match tag {
+                event::ROOT_TAG => OwnedEvent::Root { fact: fact },
+                event::NODE_TAG => OwnedEvent::Node { fact: fact, parent_hash: parents[0] },
+                _ => OwnedEvent::Merge { fact: fact, parents: parents },
+            };
+            let hash = event.hash();
+            order.push(hash);
+            events.insert(hash, event);
+        }
+
+        let head = if has_head {
+            let mut index = [0u8; 8];
+            index.copy_from_slice(reader.take(8)?);
+            let index = u64::from_be_bytes(index);
+            let head = order.get(index as usize).ok_or(LoadError::BadReference(index))?;
+            Some(*head)
+        } else {
+            None
+        };
+        if reader.remaining() != 0 {
+            return Err(LoadError::Decode(DecodeError::TrailingBytes(reader.remaining())));
+        }
+        Ok(OwnedFragment { head: head, events: events })
+    }
+}
+
+/// Iterates over every stored Event in no particular order; see
+/// `Fragment::iter`.
+impl<'f, 'a, H: EventHash> IntoIterator for &'f Fragment<'a, H> {
+    type Item = &'f Event<'a, H>;
+    type IntoIter = map::Values<'f, H, Event<'a, H>>;
+
+    fn into_iter(self) -> map::Values<'f, H, Event<'a, H>> {
+        self.iter()
+    }
+}
+
+/// Appends the Facts in order, so the first is the root and the last the
+/// head.
+///
+/// ```
+/// use pender::fragment::Fragment;
+///
+/// let facts: [&[u8]; 2] = [b"first", b"second"];
+/// let frag: Fragment = facts.iter().cloned().collect();
+/// assert_eq!(frag.depth(), 2);
+/// ```
+impl<'a, H: EventHash> FromIterator<&'a [u8]> for Fragment<'a, H> {
+    fn from_iter<I: IntoIterator<Item = &'a [u8]>>(facts: I) -> Fragment<'a, H> {
+        let mut frag = Fragment::default();
+        frag.extend(facts);
+        frag
+    }
+}
+
+/// Appends the Facts in order onto the current head.
+///
+/// # Panics
+///
+/// If the Fragment is sealed (see `Fragment::seal`), as `extend` can't
+/// fail; use `append_all` to get an error instead.
+impl<'a, H: EventHash> Extend<&'a [u8]> for Fragment<'a, H> {
+    fn extend<I: IntoIterator<Item = &'a [u8]>>(&mut self, facts: I) {
+        for fact in facts {
+            self.append(fact).expect("can't extend a sealed Fragment");
+        }
+    }
+}
+
+/// Check a proof from `Fragment::inclusion_proof` against a trusted `head`
+/// hash, without needing the Fragment itself.
+///
+/// The proof is valid if its first Event hashes to `head`, its last to
+/// `target`, and each Event's `parent` is the hash of the one after it.
+/// Events are hashed without a domain or key, so proofs from a Fragment
+/// with either need `verify_inclusion_with`.
+///
+/// ```
+/// use pender::fragment::{verify_inclusion, Fragment};
+///
+/// let mut frag = Fragment::new();
+/// frag.append(b"old").unwrap();
+/// let old = frag.head.unwrap().hash();
+/// frag.append(b"new").unwrap();
+/// let head = frag.head.unwrap().hash();
+///
+/// let proof: Vec<_> = frag.inclusion_proof(&old).unwrap()
+///     .into_iter().map(|event| event.to_owned()).collect();
+/// assert!(verify_inclusion(&head, &old, &proof));
+/// ```
+pub fn verify_inclusion<H: EventHash>(head: &H, target: &H, proof: &[OwnedEvent<H>]) -> bool {
+    verify_inclusion_with(b"", b"", head, target, proof)
+}
+
+/// Like `verify_inclusion`, hashing each Event under `key` and in `domain`
+/// (see `Event::hash_with`), as the Fragment the proof came from does.
+///
+/// ```
+/// use pender::fragment::{verify_inclusion, verify_inclusion_with, Fragment};
+///
+/// let mut frag = Fragment::with_key(b"secret".to_vec());
+/// let old = frag.append(b"old").unwrap();
+/// let head = frag.append(b"new").unwrap();
+///
+/// let proof: Vec<_> = frag.inclusion_proof(&old).unwrap()
+///     .into_iter().map(|event| event.to_owned()).collect();
+/// assert!(verify_inclusion_with(b"secret", b"", &head, &old, &proof));
+/// assert!(!verify_inclusion(&head, &old, &proof));
+/// ```
+pub fn verify_inclusion_with<H: EventHash>(key: &[u8], domain: &[u8], head: &H, target: &H,
+                                           proof: &[OwnedEvent<H>]) -> bool
+{
+    let mut expected = Some(*head);
+    for event in proof {
+        if expected != Some(event.as_borrowed().hash_with(key, domain)) {
+            return false;
+        }
+        expected = event.parent();
+    }
+    proof.last().is_some_and(|last| last.as_borrowed().hash_with(key, domain) == *target)
+}
+
+/// Milliseconds since the Unix epoch, or 0 if the clock is set before it.
+#[cfg(feature = "std")]
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// There is no clock without `std`, so every Event is timestamped 0.
+#[cfg(not(feature = "std"))]
+fn now() -> u64 {
+    0
+}
+
+#[cfg(feature = "std")]
+pub(crate) const MAGIC: &[u8] = b"PNDR";
+#[cfg(feature = "std")]
+pub(crate) const VERSION: u8 = 3;
+/// Magic bytes, version, event count and head marker.
+#[cfg(feature = "std")]
+pub(crate) const HEADER_LEN: usize = 4 + 1 + 8 + 1;
+
+/// Version byte of the `write_compact` format.
+#[cfg(feature = "std")]
+const COMPACT_VERSION: u8 = 4;
+
+/// How far back `write_compact` can refer to an already-written parent.
+#[cfg(feature = "std")]
+const COMPACT_WINDOW: usize = 255;
+
+/// A Fragment whose Facts are owned rather than borrowed, as produced by
+/// `Fragment::read_from`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OwnedFragment {
+    pub head: Option<Blake2>,
+    pub events: Map<Blake2, OwnedEvent>,
+}
+
+impl OwnedFragment {
+    /// A Fragment borrowing this one's Facts. It has no head if `head`
+    /// names an Event that isn't in `events`.
+    pub fn as_fragment<'a>(&'a self) -> Fragment<'a> {
+        Fragment {
+            head: self.head
+                .and_then(|head| self.events.get(&head))
+                .map(|event| event.as_borrowed()),
+            events: self.events.iter()
+                .map(|(&hash, event)| (hash, event.as_borrowed()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Reasons `Fragment::read_from`, `Fragment::read_compact`,
+/// `Archive::read_from` and `FragmentStreamReader` can fail.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    /// The input doesn't start with the Pender magic bytes.
+    BadMagic,
+    /// The input was written in a format version this library can't read.
+    UnsupportedVersion(u8),
+    /// An Event, or the header, could not be decoded.
+    Decode(DecodeError),
+    /// The head hash is not the hash of any Event in the input.
+    MissingHead(Blake2),
+    /// A back-reference in the compact format points outside the Events
+    /// read so far.
+    BadReference(u64),
+    /// The name of a Fragment in an `Archive` is not UTF-8.
+    BadName,
+}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for LoadError {
+    fn from(err: io::Error) -> LoadError {
+        LoadError::Io(err)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<DecodeError> for LoadError {
+    fn from(err: DecodeError) -> LoadError {
+        LoadError::Decode(err)
+    }
+}
+
+/// Ways in which `Fragment::verify` can find a Fragment to be inconsistent.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum IntegrityError<H: EventHash = Blake2> {
+    /// An Event is stored under a key other than its own hash.
+    HashMismatch { stored: H, computed: H },
+    /// A parent of a Node or Merge is not in the Fragment.
+    MissingParent(H),
+}
+
+/// The result of `Fragment::diff`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FragmentDiff<H: EventHash = Blake2> {
+    /// Hashes of Events only in the Fragment `diff` was called on, sorted.
+    pub only_self: Vec<H>,
+    /// Hashes of Events only in the other Fragment, sorted.
+    pub only_other: Vec<H>,
+    /// Number of Events in both.
+    pub common: usize,
+}
+
+impl<H: EventHash> Default for FragmentDiff<H> {
+    fn default() -> FragmentDiff<H> {
+        FragmentDiff { only_self: Vec::new(), only_other: Vec::new(), common: 0 }
+    }
+}
+
+/// The result of `Fragment::stats`.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct FragmentStats {
+    /// Number of stored Events (see `Fragment::len`).
+    pub total_events: usize,
+    /// Length of the chain from the head (see `Fragment::depth`).
+    pub depth: usize,
+    /// Number of Root Events.
+    pub root_count: usize,
+    /// Number of Events that are nobody's parent.
+    pub leaf_count: usize,
+    /// Number of Events with a parent that is not stored.
+    pub orphan_count: usize,
+    /// Total length of every stored Fact, in bytes.
+    pub total_fact_bytes: usize,
+}
+
+/// Reasons `Fragment::graft` can fail.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GraftError<H: EventHash = Blake2> {
+    /// The Event to graft, or one of its ancestors, is not in the source.
+    NotFound(H),
+    /// The Fragment being grafted onto has been sealed (see
+    /// `Fragment::seal`).
+    Sealed,
+}
+
+/// Reasons `Fragment::merge` can fail.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MergeError {
+    /// The other Fragment's domain differs from this one's.
+    DomainMismatch,
+    /// The other Fragment's key differs from this one's.
+    KeyMismatch,
+    /// This Fragment has been sealed (see `Fragment::seal`).
+    Sealed,
+}
+
+/// Reasons `Fragment::slice` can fail.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SliceError<H: EventHash = Blake2> {
+    /// The Event to slice down from is not stored.
+    NotFound(H),
+    /// Walking down from `to` never reaches `from`.
+    NotAncestor,
+}
+
+/// Reasons `Fragment::topo_sort` can fail.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TopoSortError<H: EventHash = Blake2> {
+    /// The parent links form a cycle. Holds the hashes of the Events that
+    /// could not be ordered, sorted.
+    Cycle(Vec<H>),
+}
+
+/// Reasons `Fragment::resolve_prefix` can fail.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ResolveError<H: EventHash = Blake2> {
+    /// No stored Event's hash starts with the prefix.
+    NotFound,
+    /// More than one does. Holds their hashes, sorted.
+    Ambiguous(Vec<H>),
+}
+
+/// Reasons `Fragment::try_append` and `Fragment::try_append_event` can
+/// refuse an Event.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AppendError<H: EventHash = Blake2> {
+    /// A parent of the Event is not in the Fragment.
+    MissingParent(H),
+    /// The Fact is `size` bytes long, over the Fragment's `limit`.
+    FactTooLarge { size: usize, limit: usize },
+    /// The Fragment has been sealed (see `Fragment::seal`).
+    Sealed,
+}
+
+/// A walk from a Fragment's head back to its root (see
+/// `Fragment::summarize`).
+///
+/// The Chain borrows its Fragment for `'f`, so the Fragment can't be
+/// changed while the Chain is alive. The Events it yields only borrow
+/// their Facts for `'a`, though, so they outlive the Chain.
+///
+/// Cloning a Chain forks the walk: each copy continues from the same
+/// position independently. Clones share the Fragment, so they are cheap.
+#[derive(Clone, Debug)]
+pub struct Chain<'f, 'a: 'f, H: EventHash = Blake2> {
+    fragment: &'f Fragment<'a, H>,
+    summary: &'f str,
+    next: Option<H>,
+}
+
+impl<'f, 'a, H: EventHash> Chain<'f, 'a, H> {
+    pub fn new(fragment: &'f Fragment<'a, H>, summary: &'f str) -> Chain<'f, 'a, H> {
+        let head = fragment.head;
+        Chain {
+            fragment: fragment,
+            summary: summary,
+            next: head.map(|e| fragment.hash_of(e))
+        }
+    }
+
+    /// The Event that `next_event` would return next, without advancing
+    /// the Chain. None at a terminus.
+    pub fn peek(&self) -> Option<&'f Event<'a, H>> {
+        self.next.and_then(|hash| self.fragment.events.get(&hash))
+    }
+
+    pub fn next_event(&mut self) -> Link<'a, H> {
+        match self.next {
+            None => Link::Terminus(None),
+            Some(hash) => {
+                if let Some(&event) = self.fragment.events.get(&hash) {
+                    self.set_next();
+                    Link::Event(event)
+                } else {
+                    Link::Terminus(Some(hash))
+                }
+            }
+        }
+    }
+
+    /// The hash of the missing Event the Chain is stuck on, if it has run
+    /// into a broken link; None if it ended at a root, or hasn't ended.
+    ///
+    /// Useful after iterating, which drops the hash held by
+    /// `Link::Terminus`.
+    pub fn last_terminus(&self) -> Option<H> {
+        self.next.filter(|hash| !self.fragment.contains(hash))
+    }
+
+    /// The Facts of the Chain's Events, from head to root. Stops at the
+    /// first missing link, like the Iterator.
+    pub fn facts(self) -> impl Iterator<Item = &'a [u8]> + 'f {
+        self.map(|event| event.fact())
+    }
+
+    /// Only the Events whose Facts satisfy `pred`, newest first, e.g. to
+    /// pick out Facts of one kind by a tag they start with.
+    ///
+    /// ```
+    /// use pender::fragment::Fragment;
+    ///
+    /// let facts: [&[u8]; 3] = [b"TX:one", b"NOTE:two", b"TX:three"];
+    /// let frag: Fragment = facts.iter().cloned().collect();
+    /// let txs = frag.summarize("x").filter_facts(|fact| fact.starts_with(b"TX:"));
+    /// assert_eq!(txs.count(), 2);
+    /// ```
+    pub fn filter_facts<F>(self, pred: F) -> impl Iterator<Item = Event<'a, H>> + 'f
+        where F: Fn(&[u8]) -> bool + 'f
+    {
+        self.filter(move |event| pred(event.fact()))
+    }
+
+    /// At most the `n` next Events, newest first, for showing a long log a
+    /// page at a time. Stops sooner at the first missing link, like the
+    /// Iterator.
+    ///
+    /// ```
+    /// use pender::fragment::Fragment;
+    ///
+    /// let facts: [&[u8]; 4] = [b"one", b"two", b"three", b"four"];
+    /// let frag: Fragment = facts.iter().cloned().collect();
+    /// let page: Vec<&[u8]> = frag.summarize("log").take_events(2)
+    ///     .map(|event| event.fact())
+    ///     .collect();
+    /// assert_eq!(page, [&b"four"[..], b"three"]);
+    /// ```
+    pub fn take_events(self, n: usize) -> impl Iterator<Item = Event<'a, H>> + 'f {
+        self.take(n)
+    }
+
+    /// Advance `next` to the parent of the event it currently points at.
+    ///
+    /// Returns false, leaving `next` untouched, if there is no current event
+    /// (the Chain is at a terminus).
+    fn set_next(&mut self) -> bool {
+        let parent = self.next
+            .and_then(|hash| self.fragment.events.get(&hash))
+            .map(|event| event.parent());
+        match parent {
+            Some(parent) => {
+                self.next = parent;
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+/// Walks the Chain from head to root, stopping at the first `Link::Terminus`.
+///
+/// If the Chain is broken, the missing hash is not lost: once the iterator
+/// is exhausted, `next_event` keeps returning the same
+/// `Link::Terminus(Some(hash))`.
+impl<'f, 'a, H: EventHash> Iterator for Chain<'f, 'a, H> {
+    type Item = Event<'a, H>;
+
+    fn next(&mut self) -> Option<Event<'a, H>> {
+        match self.next_event() {
+            Link::Event(event) => Some(event),
+            Link::Terminus(_) => None,
+        }
+    }
+}
+
+/// Builds a Fragment one Fact at a time, each Event the parent of the
+/// next.
+///
+/// ```
+/// use pender::fragment::EventChainBuilder;
+///
+/// let frag = EventChainBuilder::new().root(b"a").then(b"b").then(b"c").build();
+/// assert_eq!(frag.depth(), 3);
+/// assert_eq!(frag.head.unwrap().fact(), b"c");
+/// ```
+#[derive(Clone, Debug)]
+pub struct EventChainBuilder<'a, H: EventHash = Blake2> {
+    fragment: Fragment<'a, H>,
+}
+
+impl<'a> EventChainBuilder<'a> {
+    pub fn new() -> EventChainBuilder<'a> {
+        Default::default()
+    }
+}
+
+impl<'a, H: EventHash> Default for EventChainBuilder<'a, H> {
+    fn default() -> EventChainBuilder<'a, H> {
+        EventChainBuilder { fragment: Fragment::default() }
+    }
+}
+
+impl<'a, H: EventHash> EventChainBuilder<'a, H> {
+    /// Start a new chain with a Root holding `fact`. Events added before
+    /// stay in the Fragment as a separate history.
+    pub fn root(mut self, fact: &'a [u8]) -> EventChainBuilder<'a, H> {
+        self.fragment.append_event(Event::Root { fact: fact })
+            .expect("the builder's Fragment is never sealed");
+        self
+    }
+
+    /// Add `fact` on top of the last Event (as a Root, if there is none).
+    pub fn then(mut self, fact: &'a [u8]) -> EventChainBuilder<'a, H> {
+        self.fragment.append(fact).expect("the builder's Fragment is never sealed");
+        self
+    }
+
+    /// The Fragment, with its head at the last Event added.
+    pub fn build(self) -> Fragment<'a, H> {
+        self.fragment
+    }
+}
+
+/// Iterator returned by `Fragment::ancestors`.
+#[derive(Clone, Debug)]
+pub struct Ancestors<'f, 'a: 'f, H: EventHash = Blake2> {
+    fragment: &'f Fragment<'a, H>,
+    next: Option<H>,
+}
+
+impl<'f, 'a, H: EventHash> Iterator for Ancestors<'f, 'a, H> {
+    type Item = &'f Event<'a, H>;
+
+    fn next(&mut self) -> Option<&'f Event<'a, H>> {
+        let event = self.next.and_then(|hash| self.fragment.get(&hash));
+        self.next = event.and_then(|event| event.parent());
+        event
+    }
+}
+
+/// Iterator returned by `Fragment::replay`.
+#[derive(Clone, Debug)]
+pub struct Replay<'a, H: EventHash = Blake2> {
+    /// The remaining Events, head first, so the next one is at the end.
+    events: Vec<Event<'a, H>>,
+}
+
+impl<'a, H: EventHash> Iterator for Replay<'a, H> {
+    type Item = Event<'a, H>;
+
+    fn next(&mut self) -> Option<Event<'a, H>> {
+        self.events.pop()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.events.len(), Some(self.events.len()))
+    }
+}
+
+impl<'a, H: EventHash> ExactSizeIterator for Replay<'a, H> { }
+
+#[derive(Debug, PartialEq)]
+pub enum Link<'a, H: EventHash = Blake2> {
+    Event(Event<'a, H>),
+    Terminus(Option<H>),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use event::{CachedEvent, Event, OwnedEvent};
+    use hash::{Blake2, Blake2b, Blake2s, CountingHash};
+    use super::{verify_inclusion, verify_inclusion_with, AppendError, DEFAULT_BRANCH,
+                EventChainBuilder, Fragment, FragmentDiff, FragmentStats, GraftError,
+                IntegrityError, Link, MergeError, OwnedFragment, ResolveError, SliceError,
+                TopoSortError};
+    #[cfg(feature = "std")]
+    use super::{HEADER_LEN, LoadError};
+    #[cfg(feature = "std")]
+    use event::DecodeError;
+    use summary::Summary;
+
+    #[test]
+    fn chain_iterates_from_head_to_root() {
+        let mut frag = Fragment::new();
+        frag.append(b"one").unwrap();
+        frag.append(b"two").unwrap();
+        frag.append(b"three").unwrap();
+
+        let root = Event::new(b"one", None);
+        let middle = Event::new(b"two", Some(root));
+        let head = Event::new(b"three", Some(middle));
+
+        let events: Vec<Event> = frag.summarize("test").collect();
+        assert_eq!(events, vec![head, middle, root]);
+    }
+
+    #[test]
+    fn chain_iterator_over_empty_fragment_is_empty() {
+        assert_eq!(Fragment::new().summarize("test").count(), 0);
+    }
+
+    #[test]
+    fn broken_chain_stops_and_keeps_missing_hash() {
+        let root = Event::new(b"one", None);
+        let middle = Event::new(b"two", Some(root));
+        let mut frag = Fragment::new();
+        frag.append_event(middle).unwrap();
+
+        let mut chain = frag.summarize("test");
+        assert_eq!(chain.next(), Some(middle));
+        assert_eq!(chain.next(), None);
+        assert_eq!(chain.next_event(), Link::Terminus(Some(root.hash())));
+    }
+
+    #[test]
+    fn facts_come_in_reverse_insertion_order() {
+        let frag = chain(&[b"one", b"two", b"three"]);
+        let facts: Vec<&[u8]> = frag.summarize("test").facts().collect();
+        assert_eq!(facts, vec![&b"three"[..], &b"two"[..], &b"one"[..]]);
+    }
+
+    #[test]
+    fn facts_stop_at_missing_link() {
+        let mut frag = chain(&[b"one", b"two", b"three"]);
+        let root = Event::new(b"one", None);
+        frag.events.remove(&Event::new(b"two", Some(root)).hash());
+        let facts: Vec<&[u8]> = frag.summarize("test").facts().collect();
+        assert_eq!(facts, vec![&b"three"[..]]);
+    }
+
+    #[test]
+    fn set_next_walks_to_root() {
+        let mut frag = Fragment::new();
+        frag.append(b"one").unwrap();
+        frag.append(b"two").unwrap();
+        let head = frag.head.unwrap();
+
+        let mut chain = frag.summarize("test");
+        assert_eq!(chain.next, Some(head.hash()));
+        assert!(chain.set_next());
+        assert_eq!(chain.next, head.parent());
+        assert!(chain.set_next());
+        assert_eq!(chain.next, None);
+        assert!(!chain.set_next());
+        assert_eq!(chain.next, None);
+    }
+
+    #[test]
+    fn set_next_stops_at_missing_link() {
+        let root = Event::new(b"one", None);
+        let mut frag = Fragment::new();
+        frag.append_event(Event::new(b"two", Some(root))).unwrap();
+
+        let mut chain = frag.summarize("test");
+        assert!(chain.set_next());
+        assert_eq!(chain.next, Some(root.hash()));
+        assert!(!chain.set_next());
+        assert_eq!(chain.next, Some(root.hash()));
+    }
+
+    fn chain<'a>(facts: &[&'a [u8]]) -> Fragment<'a> {
+        let mut frag = Fragment::new();
+        for &fact in facts {
+            frag.append(fact).unwrap();
+        }
+        frag
+    }
+
+    #[test]
+    fn merge_disjoint_chains_takes_deeper_head() {
+        let mut mine = chain(&[b"a"]);
+        let theirs = chain(&[b"x", b"y"]);
+        let their_head = theirs.head;
+
+        mine.merge(theirs).unwrap();
+        assert_eq!(mine.events.len(), 3);
+        assert_eq!(mine.head, their_head);
+    }
+
+    #[test]
+    fn merge_equally_deep_chains_keeps_own_head() {
+        let mut mine = chain(&[b"a", b"b"]);
+        let my_head = mine.head;
+
+        mine.merge(chain(&[b"x", b"y"])).unwrap();
+        assert_eq!(mine.events.len(), 4);
+        assert_eq!(mine.head, my_head);
+    }
+
+    #[test]
+    fn merge_overlapping_chains_dedups_shared_events() {
+        let mut mine = chain(&[b"root", b"mine"]);
+        let theirs = chain(&[b"root", b"theirs", b"more"]);
+        let their_head = theirs.head;
+
+        mine.merge(theirs).unwrap();
+        assert_eq!(mine.events.len(), 4);
+        assert_eq!(mine.head, their_head);
+    }
+
+    #[test]
+    fn merge_ancestor_chain_keeps_descendant_head() {
+        let mut mine = chain(&[b"a", b"b", b"c"]);
+        let my_head = mine.head;
+
+        mine.merge(chain(&[b"a", b"b"])).unwrap();
+        assert_eq!(mine.events.len(), 3);
+        assert_eq!(mine.head, my_head);
+
+        let mut older = chain(&[b"a"]);
+        older.merge(chain(&[b"a", b"b", b"c"])).unwrap();
+        assert_eq!(older.head, my_head);
+    }
+
+    #[test]
+    fn merge_with_empty_fragment() {
+        let mut mine = chain(&[b"a", b"b"]);
+        let my_head = mine.head;
+        mine.merge(Fragment::new()).unwrap();
+        assert_eq!(mine.events.len(), 2);
+        assert_eq!(mine.head, my_head);
+
+        let mut empty = Fragment::new();
+        empty.merge(chain(&[b"a", b"b"])).unwrap();
+        assert_eq!(empty.events.len(), 2);
+        assert_eq!(empty.head, my_head);
+    }
+
+    #[test]
+    fn merge_refuses_other_domain_or_key() {
+        let mut mine = Fragment::new_with_domain(b"mine");
+        mine.append(b"a").unwrap();
+        let mut theirs = Fragment::new_with_domain(b"theirs");
+        theirs.append(b"b").unwrap();
+        assert_eq!(mine.merge(theirs), Err(MergeError::DomainMismatch));
+        assert_eq!(mine.len(), 1);
+
+        let mut keyed = Fragment::with_key(b"secret".to_vec());
+        assert_eq!(keyed.merge(chain(&[b"a"])), Err(MergeError::KeyMismatch));
+        assert!(keyed.is_empty());
+    }
+
+    #[test]
+    fn merge_carries_summaries_over() {
+        let mut mine = chain(&[b"a"]);
+        let shared = mine.checkpoint("count", b"1").unwrap();
+        mine.add_summary(shared);
+        let mut theirs = chain(&[b"a", b"b"]);
+        theirs.add_summary(shared);
+        let newer = theirs.checkpoint("count", b"2").unwrap();
+        theirs.add_summary(newer);
+
+        mine.merge(theirs).unwrap();
+        assert_eq!(mine.summaries(), &[shared, newer]);
+        assert_eq!(mine.latest_summary(), Some(&newer));
+    }
+
+    #[test]
+    fn get_and_contains_find_stored_events() {
+        let frag = chain(&[b"a", b"b"]);
+        let head = frag.head.unwrap();
+        let root = Event::new(b"a", None);
+
+        assert_eq!(frag.get(&head.hash()), Some(&head));
+        assert!(frag.contains(&head.hash()));
+        assert_eq!(frag.get(&root.hash()), Some(&root));
+        assert!(frag.contains(&root.hash()));
+    }
+
+    #[test]
+    fn get_and_contains_miss_absent_events() {
+        let frag = chain(&[b"a", b"b"]);
+        let absent = Event::new(b"c", frag.head).hash();
+        assert_eq!(frag.get(&absent), None);
+        assert!(!frag.contains(&absent));
+        assert!(!Fragment::new().contains(&absent));
+    }
+
+    #[test]
+    fn len_and_depth_of_empty_fragment() {
+        let frag = Fragment::new();
+        assert_eq!(frag.len(), 0);
+        assert!(frag.is_empty());
+        assert_eq!(frag.depth(), 0);
+    }
+
+    #[test]
+    fn len_and_depth_of_single_root() {
+        let frag = chain(&[b"a"]);
+        assert_eq!(frag.len(), 1);
+        assert!(!frag.is_empty());
+        assert_eq!(frag.depth(), 1);
+    }
+
+    #[test]
+    fn len_and_depth_of_linear_chain() {
+        let frag = chain(&[b"a", b"b", b"c"]);
+        assert_eq!(frag.len(), 3);
+        assert_eq!(frag.depth(), 3);
+    }
+
+    #[test]
+    fn off_chain_events_count_towards_len_not_depth() {
+        let mut frag = chain(&[b"a", b"b", b"c"]);
+        let head = frag.head;
+        frag.append_event(Event::new(b"x", None)).unwrap();
+        frag.head = head;
+        assert_eq!(frag.len(), 4);
+        assert_eq!(frag.depth(), 3);
+    }
+
+    #[test]
+    fn depth_stops_at_missing_link() {
+        let mut frag = chain(&[b"a", b"b", b"c"]);
+        let root = Event::new(b"a", None);
+        let middle = Event::new(b"b", Some(root));
+        frag.events.remove(&middle.hash());
+        assert_eq!(frag.depth(), 1);
+    }
+
+    #[test]
+    fn verify_accepts_well_formed_fragments() {
+        assert_eq!(Fragment::new().verify(), Ok(()));
+        assert_eq!(chain(&[b"a", b"b", b"c"]).verify(), Ok(()));
+    }
+
+    #[test]
+    fn verify_rejects_event_under_wrong_key() {
+        let mut frag = chain(&[b"a"]);
+        let root = frag.head.unwrap();
+        let wrong = Event::new(b"b", None);
+        frag.events.insert(wrong.hash(), root);
+        frag.events.remove(&root.hash());
+
+        assert_eq!(frag.verify(),
+                   Err(IntegrityError::HashMismatch { stored: wrong.hash(), computed: root.hash() }));
+    }
+
+    #[test]
+    fn reindex_repairs_wrong_keys() {
+        let mut frag = chain(&[b"a", b"b"]);
+        let head = frag.head.unwrap();
+        let root = Event::new(b"a", None);
+        let wrong = Event::new(b"wrong", None).hash();
+        frag.events.remove(&root.hash());
+        frag.events.insert(wrong, root);
+        assert!(frag.verify().is_err());
+
+        assert_eq!(frag.reindex(), 1);
+        assert_eq!(frag.verify(), Ok(()));
+        assert_eq!(frag.events.get(&root.hash()), Some(&root));
+        assert!(!frag.contains(&wrong));
+        assert_eq!(frag.head, Some(head));
+        assert_eq!(frag.depth(), 2);
+        assert_eq!(frag.reindex(), 0);
+    }
+
+    #[test]
+    fn verify_event_checks_one_event() {
+        let frag = chain(&[b"1", b"2"]);
+        let node = Event::new(b"3", frag.head);
+        assert_eq!(frag.verify_event(&node, &node.hash()), Ok(()));
+
+        let tampered = Event::new(b"4", frag.head);
+        let (stored, computed) = (node.hash(), tampered.hash());
+        assert_eq!(frag.verify_event(&tampered, &stored),
+                   Err(IntegrityError::HashMismatch { stored: stored, computed: computed }));
+
+        let orphan = Event::new(b"5", Some(node));
+        assert_eq!(frag.verify_event(&orphan, &orphan.hash()),
+                   Err(IntegrityError::MissingParent(node.hash())));
+    }
+
+    #[test]
+    fn verify_rejects_missing_parent() {
+        let root = Event::new(b"a", None);
+        let mut frag = Fragment::new();
+        frag.append_event(Event::new(b"b", Some(root))).unwrap();
+        assert_eq!(frag.verify(), Err(IntegrityError::MissingParent(root.hash())));
+    }
+
+    #[test]
+    fn complete_chain_has_no_orphans() {
+        assert!(chain(&[b"a", b"b", b"c"]).orphans().is_empty());
+        assert!(Fragment::new().orphans().is_empty());
+    }
+
+    #[test]
+    fn chain_missing_its_root_has_one_orphan() {
+        let mut frag = chain(&[b"a", b"b", b"c"]);
+        let root = Event::new(b"a", None);
+        let middle = Event::new(b"b", Some(root));
+        frag.events.remove(&root.hash());
+        assert_eq!(frag.orphans(), vec![middle.hash()]);
+    }
+
+    #[test]
+    fn independent_dangling_nodes_are_all_orphans() {
+        let root = Event::new(b"a", None);
+        let left = Event::new(b"left", Some(Event::new(b"l", Some(root))));
+        let right = Event::new(b"right", Some(Event::new(b"r", Some(root))));
+        let mut frag = Fragment::new();
+        frag.append_event(root).unwrap();
+        frag.append_event(left).unwrap();
+        frag.append_event(right).unwrap();
+
+        let orphans = frag.orphans();
+        assert_eq!(orphans.len(), 2);
+        assert!(orphans.contains(&left.hash()));
+        assert!(orphans.contains(&right.hash()));
+    }
+
+    #[test]
+    fn chain_follows_first_parent_of_merge() {
+        let mut frag = chain(&[b"root", b"left"]);
+        let left = frag.head.unwrap();
+        let root = Event::new(b"root", None);
+        let right = Event::new(b"right", Some(root));
+        frag.append_event(right).unwrap();
+
+        let parents = [left.hash(), right.hash()];
+        let merge = Event::new_merge(b"merge", &parents);
+        frag.append_event(merge).unwrap();
+
+        assert_eq!(frag.verify(), Ok(()));
+        let events: Vec<Event> = frag.summarize("test").collect();
+        assert_eq!(events, vec![merge, left, root]);
+    }
+
+    #[test]
+    fn merge_with_missing_parent_is_an_orphan() {
+        let mut frag = chain(&[b"root"]);
+        let root = frag.head.unwrap();
+        let absent = Event::new(b"absent", Some(root)).hash();
+        let parents = [root.hash(), absent];
+        let merge = Event::new_merge(b"merge", &parents);
+        frag.append_event(merge).unwrap();
+
+        assert_eq!(frag.orphans(), vec![merge.hash()]);
+        assert_eq!(frag.verify(), Err(IntegrityError::MissingParent(absent)));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn fragment_round_trips_through_writer() {
+        let mut frag = chain(&[b"one", b"two", b"three"]);
+        let root = Event::new(b"one", None).hash();
+        let parents = [frag.head.unwrap().hash(), root];
+        frag.append_event(Event::new_merge(b"merge", &parents)).unwrap();
+
+        let mut buf = Vec::new();
+        frag.write_to(&mut buf).unwrap();
+        let loaded = Fragment::read_from(&mut &buf[..]).unwrap();
+        let reloaded = loaded.as_fragment();
+
+        assert_eq!(reloaded.head, frag.head);
+        assert_eq!(reloaded.events, frag.events);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn empty_fragment_round_trips_through_writer() {
+        let mut buf = Vec::new();
+        Fragment::new().write_to(&mut buf).unwrap();
+        let loaded = Fragment::read_from(&mut &buf[..]).unwrap();
+        assert_eq!(loaded.head, None);
+        assert!(loaded.events.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn read_from_rejects_bad_header() {
+        let mut buf = Vec::new();
+        chain(&[b"one"]).write_to(&mut buf).unwrap();
+
+        let mut bad_magic = buf.clone();
+        bad_magic[0] = b'X';
+        match Fragment::read_from(&mut &bad_magic[..]) {
+            Err(LoadError::BadMagic) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let mut bad_version = buf.clone();
+        bad_version[4] = 99;
+        match Fragment::read_from(&mut &bad_version[..]) {
+            Err(LoadError::UnsupportedVersion(99)) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn read_from_rejects_truncated_input() {
+        let mut buf = Vec::new();
+        chain(&[b"one", b"two"]).write_to(&mut buf).unwrap();
+        for len in 0..buf.len() {
+            match Fragment::read_from(&mut &buf[..len]) {
+                Err(LoadError::Decode(DecodeError::Truncated)) => (),
+                other => panic!("unexpected {:?} at length {}", other, len),
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn read_from_rejects_missing_head() {
+        let mut frag = chain(&[b"one", b"two"]);
+        let head = frag.head.unwrap();
+        frag.events.remove(&head.hash());
+
+        let mut buf = Vec::new();
+        frag.write_to(&mut buf).unwrap();
+        match Fragment::read_from(&mut &buf[..]) {
+            Err(LoadError::MissingHead(hash)) => assert_eq!(hash, head.hash()),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn fragment_round_trips_through_compact_format() {
+        let facts: Vec<Vec<u8>> = (0..300u32).map(|i| i.to_be_bytes().to_vec()).collect();
+        let mut frag: Fragment = facts.iter().map(|fact| &fact[..]).collect();
+        let root = Event::new(&facts[0], None).hash();
+        let parents = [frag.head.unwrap().hash(), root];
+        frag.append_event(Event::new_merge(b"merge", &parents)).unwrap();
+        frag.append_event(Event::new(b"other root", None)).unwrap();
+
+        let mut buf = Vec::new();
+        frag.write_compact(&mut buf).unwrap();
+        let loaded = Fragment::read_compact(&mut &buf[..]).unwrap();
+        assert_eq!(loaded.as_fragment(), frag);
+
+        let mut buf = Vec::new();
+        Fragment::new().write_compact(&mut buf).unwrap();
+        assert_eq!(Fragment::read_compact(&mut &buf[..]).unwrap().as_fragment(), Fragment::new());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn compact_format_is_smaller_for_chains() {
+        let facts: Vec<Vec<u8>> = (0..100u32).map(|i| i.to_be_bytes().to_vec()).collect();
+        let frag: Fragment = facts.iter().map(|fact| &fact[..]).collect();
+
+        let mut naive = Vec::new();
+        frag.write_to(&mut naive).unwrap();
+        let mut compact = Vec::new();
+        frag.write_compact(&mut compact).unwrap();
+        assert!(compact.len() * 4 < naive.len(), "{} vs {}", compact.len(), naive.len());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn read_compact_rejects_bad_input() {
+        let mut buf = Vec::new();
+        chain(&[b"one", b"two"]).write_compact(&mut buf).unwrap();
+        for len in 0..buf.len() {
+            match Fragment::read_compact(&mut &buf[..len]) {
+                Err(LoadError::Decode(DecodeError::Truncated)) => (),
+                other => panic!("unexpected {:?} at length {}", other, len),
+            }
+        }
+
+        // The second Event's parent is one back; point it two back instead.
+        let root_len = 1 + 8 + 3;
+        let mut bad_reference = buf.clone();
+        bad_reference[HEADER_LEN + root_len + 1] = 2;
+        match Fragment::read_compact(&mut &bad_reference[..]) {
+            Err(LoadError::BadReference(2)) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let mut naive = Vec::new();
+        chain(&[b"one"]).write_to(&mut naive).unwrap();
+        match Fragment::read_compact(&mut &naive[..]) {
+            Err(LoadError::UnsupportedVersion(3)) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn keyed_and_domain_fragments_are_not_written() {
+        use std::io::ErrorKind;
+
+        let mut keyed = Fragment::with_key(b"secret".to_vec());
+        keyed.append_all(&[b"1", b"2"]).unwrap();
+        let mut domain = Fragment::new_with_domain(b"db");
+        domain.append_all(&[b"1", b"2"]).unwrap();
+        for frag in &[&keyed, &domain] {
+            let mut buf = Vec::new();
+            assert_eq!(frag.write_to(&mut buf).unwrap_err().kind(), ErrorKind::InvalidInput);
+            assert_eq!(frag.write_compact(&mut buf).unwrap_err().kind(), ErrorKind::InvalidInput);
+            assert!(buf.is_empty());
+        }
+
+        // The same Events without the key round-trip.
+        let mut plain = Fragment::new();
+        plain.append_all(&[b"1", b"2"]).unwrap();
+        let mut buf = Vec::new();
+        plain.write_to(&mut buf).unwrap();
+        assert_eq!(Fragment::read_from(&mut &buf[..]).unwrap().as_fragment(), plain);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn files_hashed_the_old_way_are_unsupported() {
+        // A version 1 file holding the Root "one" as its head, whose hash
+        // was the Blake2b-512 of the Fact's length and the Fact alone.
+        let root = Event::new(b"one", None);
+        let old_hash = Blake2::new_concat(&[&3u64.to_be_bytes(), b"one"]);
+        let mut old = b"PNDR\x01".to_vec();
+        old.extend_from_slice(&1u64.to_be_bytes());
+        old.push(1);
+        old.extend_from_slice(&old_hash.bytes);
+        old.extend_from_slice(&root.to_bytes());
+        match Fragment::read_from(&mut &old[..]) {
+            Err(LoadError::UnsupportedVersion(1)) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        // The same in the old compact format, with the head as index 0.
+        let mut old_compact = b"PNDR\x02".to_vec();
+        old_compact.extend_from_slice(&1u64.to_be_bytes());
+        old_compact.push(1);
+        old_compact.extend_from_slice(&root.to_bytes());
+        old_compact.extend_from_slice(&0u64.to_be_bytes());
+        match Fragment::read_compact(&mut &old_compact[..]) {
+            Err(LoadError::UnsupportedVersion(2)) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    /// Variations on `buf`: every truncation, plus bytes and declared
+    /// lengths overwritten at pseudo-random places.
+    #[cfg(feature = "std")]
+    fn corruptions(buf: &[u8]) -> Vec<Vec<u8>> {
+        let mut corrupted: Vec<Vec<u8>> = (0..buf.len()).map(|len| buf[..len].to_vec()).collect();
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut random = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        for _ in 0..2000 {
+            let mut bad = buf.to_vec();
+            let at = random() as usize % bad.len();
+            bad[at] = random() as u8;
+            corrupted.push(bad);
+
+            let mut bad = buf.to_vec();
+            let at = random() as usize % (bad.len() - 8);
+            let len = [u64::MAX, u64::MAX / 2, random(), random() % 256][random() as usize % 4];
+            bad[at..at + 8].copy_from_slice(&len.to_be_bytes());
+            corrupted.push(bad);
+        }
+        corrupted
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn readers_never_panic_on_corrupt_input() {
+        let mut frag = chain(&[b"one", b"two", b"three"]);
+        let parents = [frag.head.unwrap().hash(), Event::new(b"one", None).hash()];
+        frag.append_event(Event::new_merge(b"merge", &parents)).unwrap();
+
+        let mut naive = Vec::new();
+        frag.write_to(&mut naive).unwrap();
+        for bad in corruptions(&naive) {
+            let _ = Fragment::read_from(&mut &bad[..]);
+        }
+
+        let mut compact = Vec::new();
+        frag.write_compact(&mut compact).unwrap();
+        for bad in corruptions(&compact) {
+            let _ = Fragment::read_compact(&mut &bad[..]);
+        }
+
+        for bad in corruptions(&frag.head.unwrap().to_bytes()) {
+            let _ = Event::from_bytes(&bad);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn read_from_rejects_oversized_count() {
+        let mut buf = Vec::new();
+        chain(&[b"one"]).write_to(&mut buf).unwrap();
+        buf[5..13].copy_from_slice(&u64::MAX.to_be_bytes());
+        match Fragment::read_from(&mut &buf[..]) {
+            Err(LoadError::Decode(DecodeError::Truncated)) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn canonical_bytes_ignore_insertion_order() {
+        let root = Event::new(b"root", None);
+        let left = Event::new(b"left", Some(root));
+        let right = Event::new(b"right", Some(root));
+        let parents = [left.hash(), right.hash()];
+        let merge = Event::new_merge(b"merge", &parents);
+
+        let mut one = Fragment::new();
+        let mut other = Fragment::with_capacity(100);
+        for &event in &[root, left, right, merge] {
+            one.append_event(event).unwrap();
+        }
+        for &event in &[right, root, merge, left] {
+            other.append_event(event).unwrap();
+        }
+        other.head = Some(merge);
+
+        assert_eq!(one.canonical_bytes(), other.canonical_bytes());
+        assert_eq!(Blake2::new(&one.canonical_bytes()), Blake2::new(&other.canonical_bytes()));
+        let loaded = Fragment::read_from(&mut &one.canonical_bytes()[..]).unwrap();
+        assert_eq!(loaded.as_fragment(), one);
+
+        other.append(b"more").unwrap();
+        assert_ne!(one.canonical_bytes(), other.canonical_bytes());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn write_compact_rejects_missing_head() {
+        let mut frag = chain(&[b"one", b"two"]);
+        let head = frag.head.unwrap();
+        frag.events.remove(&head.hash());
+
+        let mut buf = Vec::new();
+        assert!(frag.write_compact(&mut buf).is_err());
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn timestamps_do_not_change_hashes() {
+        let mut early = Fragment::new();
+        early.append_at(b"one", 1000).unwrap();
+        let mut late = Fragment::new();
+        late.append_at(b"one", 2000).unwrap();
+
+        let hash = early.head.unwrap().hash();
+        assert_eq!(late.head.unwrap().hash(), hash);
+        assert_eq!(early.timestamp(&hash), Some(1000));
+        assert_eq!(late.timestamp(&hash), Some(2000));
+    }
+
+    #[test]
+    fn append_records_current_time() {
+        let mut frag = Fragment::new();
+        let before = super::now();
+        frag.append(b"one").unwrap();
+        let after = super::now();
+
+        let stamp = frag.timestamp(&frag.head.unwrap().hash()).unwrap();
+        assert!(before <= stamp && stamp <= after);
+        assert_eq!(frag.timestamp(&Event::new(b"two", None).hash()), None);
+    }
+
+    #[test]
+    #[cfg(not(feature = "std"))]
+    fn fragment_without_std_is_kept_in_a_btree_map() {
+        let mut frag = Fragment::new();
+        let hashes = frag.append_all(&[b"one", b"two", b"three"]).unwrap();
+        frag.reindex();
+
+        let mut sorted = hashes.clone();
+        sorted.sort();
+        assert_eq!(frag.events.keys().cloned().collect::<Vec<_>>(), sorted);
+        assert_eq!(frag.timestamp(&hashes[0]), Some(0));
+        assert_eq!(frag.children_index()[&hashes[0]], vec![hashes[1]]);
+        assert_eq!(frag.verify(), Ok(()));
+    }
+
+    #[test]
+    fn reappending_keeps_first_timestamp() {
+        let root = Event::new(b"one", None);
+        let mut frag = Fragment::new();
+        frag.append_event_at(root, 1000).unwrap();
+        frag.append_event_at(root, 2000).unwrap();
+        assert_eq!(frag.timestamp(&root.hash()), Some(1000));
+    }
+
+    #[test]
+    fn iterating_a_fragment_visits_every_event() {
+        let mut frag = chain(&[b"one", b"two", b"three"]);
+        frag.append_event(Event::new(b"other", None)).unwrap();
+
+        assert_eq!(frag.iter().count(), 4);
+        let mut seen = 0;
+        for event in &frag {
+            assert_eq!(frag.get(&event.hash()), Some(event));
+            seen += 1;
+        }
+        assert_eq!(seen, 4);
+    }
+
+    #[test]
+    fn prune_from_middle_drops_older_ancestors() {
+        let mut frag = chain(&[b"1", b"2", b"3", b"4", b"5"]);
+        let head = frag.head;
+        let two = Event::new(b"2", Some(Event::new(b"1", None)));
+        let three = Event::new(b"3", Some(two)).hash();
+
+        assert_eq!(frag.prune(&three), 2);
+        assert_eq!(frag.len(), 3);
+        assert_eq!(frag.head, head);
+        assert_eq!(frag.depth(), 3);
+        assert!(frag.contains(&three));
+        assert_eq!(frag.timestamp(&Event::new(b"1", None).hash()), None);
+    }
+
+    #[test]
+    fn prune_from_head_keeps_only_head() {
+        let mut frag = chain(&[b"1", b"2", b"3", b"4", b"5"]);
+        let head = frag.head.unwrap().hash();
+        assert_eq!(frag.prune(&head), 4);
+        assert_eq!(frag.len(), 1);
+        assert_eq!(frag.depth(), 1);
+    }
+
+    #[test]
+    fn prune_from_off_chain_hash_does_nothing() {
+        let mut frag = chain(&[b"1", b"2", b"3"]);
+        let head = frag.head;
+        let other = Event::new(b"other", None);
+        frag.append_event(Event::new(b"branch", Some(other))).unwrap();
+        frag.append_event(other).unwrap();
+        frag.head = head;
+
+        assert_eq!(frag.prune(&other.hash()), 0);
+        assert_eq!(frag.prune(&Event::new(b"absent", None).hash()), 0);
+        assert_eq!(frag.len(), 5);
+    }
+
+    #[test]
+    fn prune_drops_summaries_of_removed_events() {
+        let mut frag = chain(&[b"1"]);
+        let dropped = frag.checkpoint("count", b"1").unwrap();
+        frag.add_summary(dropped);
+        frag.append(b"2").unwrap();
+        let kept = frag.checkpoint("count", b"2").unwrap();
+        frag.add_summary(kept);
+        frag.append(b"3").unwrap();
+
+        assert_eq!(frag.prune(&kept.event()), 1);
+        assert_eq!(frag.summaries(), &[kept]);
+    }
+
+    #[test]
+    fn prune_follows_stored_parent_links() {
+        // Stored under their unkeyed hashes, so only the head is found
+        // under its `hash_of`.
+        let mut frag = chain(&[b"1", b"2"]);
+        frag.key = b"key".to_vec();
+        let head = frag.head.unwrap();
+        frag.events.insert(frag.hash_of(head), head);
+        let root = Event::new(b"1", None);
+
+        assert_eq!(frag.prune(&frag.hash_of(root)), 0);
+        assert!(frag.contains(&root.hash()));
+    }
+
+    #[test]
+    fn prune_leaves_side_branches_alone() {
+        let mut frag = chain(&[b"1", b"2", b"3"]);
+        let head = frag.head;
+        let root = Event::new(b"1", None);
+        let side = Event::new(b"side", Some(root));
+        frag.append_event(side).unwrap();
+        frag.head = head;
+
+        assert_eq!(frag.prune(&head.unwrap().hash()), 2);
+        assert!(frag.contains(&side.hash()));
+    }
+
+    #[test]
+    fn retain_removes_leaf() {
+        let mut frag = chain(&[b"1", b"2", b"3"]);
+        assert_eq!(frag.retain(|event| event.fact() != b"3"), 1);
+        assert_eq!(frag, chain(&[b"1", b"2"]));
+        assert_eq!(frag.verify(), Ok(()));
+    }
+
+    #[test]
+    fn retain_skips_event_with_descendants() {
+        let mut frag = chain(&[b"1", b"2", b"3"]);
+        assert_eq!(frag.retain(|event| event.fact() != b"2"), 0);
+        assert_eq!(frag, chain(&[b"1", b"2", b"3"]));
+    }
+
+    #[test]
+    fn retain_removes_whole_suffix() {
+        let mut frag = chain(&[b"1", b"2", b"3", b"4"]);
+        let side = frag.append_to("side", b"side").unwrap();
+        assert_eq!(frag.retain(|event| event.fact() != b"3" && event.fact() != b"4"), 2);
+        assert_eq!(frag.head, chain(&[b"1", b"2"]).head);
+        assert_eq!(frag.len(), 3);
+        assert!(frag.contains(&side));
+        assert_eq!(frag.verify(), Ok(()));
+
+        assert_eq!(frag.retain(|_| false), 3);
+        assert!(frag.is_empty());
+        assert_eq!(frag.head, None);
+        assert!(frag.branch_head("side").is_none());
+    }
+
+    #[test]
+    fn retain_drops_summaries_of_removed_events() {
+        let mut frag = chain(&[b"1", b"2"]);
+        let kept = frag.checkpoint("count", b"2").unwrap();
+        frag.add_summary(kept);
+        frag.append(b"3").unwrap();
+        let dropped = frag.checkpoint("count", b"3").unwrap();
+        frag.add_summary(dropped);
+
+        assert_eq!(frag.retain(|event| event.fact() != b"3"), 1);
+        assert_eq!(frag.summaries(), &[kept]);
+        assert_eq!(frag.latest_summary(), Some(&kept));
+    }
+
+    #[test]
+    fn ancestors_of_head_match_chain() {
+        let frag = chain(&[b"1", b"2", b"3"]);
+        let head = frag.head.unwrap().hash();
+        let ancestors: Vec<Event> = frag.ancestors(&head).cloned().collect();
+        assert_eq!(ancestors, frag.summarize("test").collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn ancestors_of_mid_chain_event() {
+        let frag = chain(&[b"1", b"2", b"3"]);
+        let root = Event::new(b"1", None);
+        let middle = Event::new(b"2", Some(root));
+        let ancestors: Vec<Event> = frag.ancestors(&middle.hash()).cloned().collect();
+        assert_eq!(ancestors, vec![middle, root]);
+    }
+
+    #[test]
+    fn ancestors_of_absent_event_is_empty() {
+        let frag = chain(&[b"1", b"2", b"3"]);
+        assert_eq!(frag.ancestors(&Event::new(b"4", frag.head).hash()).count(), 0);
+    }
+
+    #[test]
+    fn parent_and_grandparent_are_ancestors() {
+        let frag = chain(&[b"1", b"2", b"3"]);
+        let root = Event::new(b"1", None);
+        let middle = Event::new(b"2", Some(root));
+        let head = frag.head.unwrap();
+
+        assert!(frag.is_ancestor_of(&middle.hash(), &head.hash()));
+        assert!(frag.is_ancestor_of(&root.hash(), &head.hash()));
+        assert!(frag.is_ancestor_of(&root.hash(), &middle.hash()));
+    }
+
+    #[test]
+    fn descendants_and_unrelated_events_are_not_ancestors() {
+        let mut frag = chain(&[b"1", b"2"]);
+        let head = frag.head.unwrap().hash();
+        let root = Event::new(b"1", None).hash();
+        let other = Event::new(b"other", None);
+        frag.append_event(other).unwrap();
+
+        assert!(!frag.is_ancestor_of(&head, &root));
+        assert!(!frag.is_ancestor_of(&other.hash(), &head));
+        assert!(!frag.is_ancestor_of(&root, &other.hash()));
+    }
+
+    #[test]
+    fn event_is_not_its_own_ancestor() {
+        let frag = chain(&[b"1", b"2"]);
+        let head = frag.head.unwrap().hash();
+        assert!(!frag.is_ancestor_of(&head, &head));
+        assert!(frag.is_ancestor_or_self(&head, &head));
+    }
+
+    #[test]
+    fn ancestry_stops_at_missing_link() {
+        let mut frag = chain(&[b"1", b"2", b"3"]);
+        let root = Event::new(b"1", None);
+        let middle = Event::new(b"2", Some(root));
+        frag.events.remove(&middle.hash());
+        assert!(!frag.is_ancestor_of(&root.hash(), &frag.head.unwrap().hash()));
+    }
+
+    #[test]
+    fn ancestry_follows_every_merge_parent() {
+        let mut frag = chain(&[b"1", b"2"]);
+        let head = frag.head.unwrap();
+        let side = Event::new(b"side", None);
+        frag.append_event(side).unwrap();
+        let parents = [head.hash(), side.hash()];
+        let merge = Event::new_merge(b"merge", &parents);
+        frag.append_event(merge).unwrap();
+
+        assert!(frag.is_ancestor_of(&side.hash(), &merge.hash()));
+        assert!(frag.is_ancestor_of(&Event::new(b"1", None).hash(), &merge.hash()));
+    }
+
+    #[test]
+    fn common_ancestor_of_siblings_is_their_parent() {
+        let mut frag = chain(&[b"1", b"2"]);
+        let parent = frag.head.unwrap();
+        let left = Event::new(b"left", Some(parent));
+        let right = Event::new(b"right", Some(parent));
+        frag.append_event(left).unwrap();
+        frag.append_event(right).unwrap();
+
+        assert_eq!(frag.common_ancestor(&left.hash(), &right.hash()), Some(parent.hash()));
+        assert_eq!(frag.common_ancestor(&right.hash(), &left.hash()), Some(parent.hash()));
+    }
+
+    #[test]
+    fn common_ancestor_on_linear_chain_is_older_event() {
+        let frag = chain(&[b"1", b"2", b"3"]);
+        let head = frag.head.unwrap().hash();
+        let middle = Event::new(b"2", Some(Event::new(b"1", None))).hash();
+
+        assert_eq!(frag.common_ancestor(&head, &middle), Some(middle));
+        assert_eq!(frag.common_ancestor(&middle, &head), Some(middle));
+        assert_eq!(frag.common_ancestor(&head, &head), Some(head));
+    }
+
+    #[test]
+    fn disjoint_roots_have_no_common_ancestor() {
+        let mut frag = chain(&[b"1"]);
+        let other = Event::new(b"other", None);
+        frag.append_event(other).unwrap();
+        let root = Event::new(b"1", None).hash();
+
+        assert_eq!(frag.common_ancestor(&root, &other.hash()), None);
+        assert_eq!(frag.common_ancestor(&root, &Event::new(b"absent", None).hash()), None);
+    }
+
+    #[test]
+    fn linear_chain_has_one_leaf() {
+        let frag = chain(&[b"1", b"2", b"3"]);
+        assert_eq!(frag.leaves(), vec![frag.head.unwrap().hash()]);
+        assert!(Fragment::new().leaves().is_empty());
+    }
+
+    #[test]
+    fn forked_fragment_has_two_leaves() {
+        let mut frag = chain(&[b"1", b"2"]);
+        let parent = frag.head.unwrap();
+        let left = Event::new(b"left", Some(parent));
+        let right = Event::new(b"right", Some(parent));
+        frag.append_event(left).unwrap();
+        frag.append_event(right).unwrap();
+
+        let leaves = frag.leaves();
+        assert_eq!(leaves.len(), 2);
+        assert!(leaves.contains(&left.hash()));
+        assert!(leaves.contains(&right.hash()));
+
+        let index = frag.children_index();
+        let children = &index[&parent.hash()];
+        assert_eq!(children.len(), 2);
+        assert!(children.contains(&left.hash()));
+        assert!(children.contains(&right.hash()));
+        assert_eq!(index[&Event::new(b"1", None).hash()], vec![parent.hash()]);
+        assert!(index[&left.hash()].is_empty());
+    }
+
+    #[test]
+    fn children_index_lists_merge_under_each_parent() {
+        let mut frag = chain(&[b"1"]);
+        let root = frag.head.unwrap();
+        let side = Event::new(b"side", None);
+        frag.append_event(side).unwrap();
+        let parents = [root.hash(), side.hash()];
+        let merge = Event::new_merge(b"merge", &parents);
+        frag.append_event(merge).unwrap();
+
+        let index = frag.children_index();
+        assert_eq!(index[&root.hash()], vec![merge.hash()]);
+        assert_eq!(index[&side.hash()], vec![merge.hash()]);
+        assert_eq!(frag.leaves(), vec![merge.hash()]);
+    }
+
+    #[test]
+    fn component_count_counts_separate_histories() {
+        assert_eq!(Fragment::new().component_count(), 0);
+
+        let mut frag = chain(&[b"1", b"2", b"3"]);
+        assert_eq!(frag.component_count(), 1);
+
+        frag.merge(chain(&[b"a", b"b"])).unwrap();
+        assert_eq!(frag.component_count(), 2);
+
+        let parents = [Event::new(b"1", None).hash(), Event::new(b"a", None).hash()];
+        frag.append_event(Event::new_merge(b"join", &parents)).unwrap();
+        assert_eq!(frag.component_count(), 1);
+    }
+
+    #[test]
+    fn append_all_chains_facts() {
+        let mut frag = chain(&[b"1"]);
+        let root = frag.head.unwrap();
+        let hashes = frag.append_all(&[b"2", b"3"]).unwrap();
+
+        let two = Event::new(b"2", Some(root));
+        let three = Event::new(b"3", Some(two));
+        assert_eq!(hashes, vec![two.hash(), three.hash()]);
+        assert_eq!(frag.head, Some(three));
+        assert_eq!(frag.len(), 3);
+    }
+
+    #[test]
+    fn append_all_onto_empty_fragment_starts_with_root() {
+        let mut frag = Fragment::new();
+        let hashes = frag.append_all(&[b"1", b"2"]).unwrap();
+        let root = Event::new(b"1", None);
+        assert_eq!(hashes, vec![root.hash(), Event::new(b"2", Some(root)).hash()]);
+        assert!(frag.append_all(&[]).unwrap().is_empty());
+        assert_eq!(frag.depth(), 2);
+    }
+
+    #[test]
+    fn try_append_event_accepts_root() {
+        let mut frag = Fragment::new();
+        let root = Event::new(b"1", None);
+        assert_eq!(frag.try_append_event(root), Ok(()));
+        assert_eq!(frag.head, Some(root));
+    }
+
+    #[test]
+    fn try_append_event_accepts_node_onto_present_parent() {
+        let mut frag = chain(&[b"1"]);
+        let node = Event::new(b"2", frag.head);
+        assert_eq!(frag.try_append_event(node), Ok(()));
+        assert_eq!(frag.head, Some(node));
+    }
+
+    #[test]
+    fn try_append_event_rejects_node_with_absent_parent() {
+        let mut frag = chain(&[b"1"]);
+        let head = frag.head;
+        let absent = Event::new(b"absent", None);
+        let node = Event::new(b"2", Some(absent));
+        assert_eq!(frag.try_append_event(node), Err(AppendError::MissingParent(absent.hash())));
+        assert_eq!(frag.head, head);
+        assert!(!frag.contains(&node.hash()));
+
+        let parents = [head.unwrap().hash(), absent.hash()];
+        let merge = Event::new_merge(b"merge", &parents);
+        assert_eq!(frag.try_append_event(merge), Err(AppendError::MissingParent(absent.hash())));
+    }
+
+    #[test]
+    fn inclusion_proof_runs_from_head_to_target() {
+        let frag = chain(&[b"1", b"2", b"3"]);
+        let head = frag.head.unwrap();
+        let two = frag.get(&head.parent().unwrap()).cloned().unwrap();
+        assert_eq!(frag.inclusion_proof(&two.hash()), Some(vec![head, two]));
+    }
+
+    #[test]
+    fn inclusion_proof_of_head_is_just_the_head() {
+        let frag = chain(&[b"1", b"2"]);
+        let head = frag.head.unwrap();
+        assert_eq!(frag.inclusion_proof(&head.hash()), Some(vec![head]));
+    }
+
+    #[test]
+    fn inclusion_proof_of_off_chain_event_is_none() {
+        let mut frag = chain(&[b"1", b"2"]);
+        let head = frag.head;
+        let side = Event::new(b"side", None);
+        frag.append_event(side).unwrap();
+        frag.head = head;
+        assert_eq!(frag.inclusion_proof(&side.hash()), None);
+        assert_eq!(Fragment::new().inclusion_proof(&side.hash()), None);
+    }
+
+    fn owned_proof(frag: &Fragment, target: &Blake2) -> Vec<OwnedEvent> {
+        frag.inclusion_proof(target).unwrap().into_iter()
+            .map(|event| event.to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn verify_inclusion_accepts_valid_proof() {
+        let frag = chain(&[b"1", b"2", b"3"]);
+        let head = frag.head.unwrap().hash();
+        let root = frag.ancestors(&head).last().unwrap().hash();
+        assert!(verify_inclusion(&head, &root, &owned_proof(&frag, &root)));
+        assert!(verify_inclusion(&head, &head, &owned_proof(&frag, &head)));
+        assert!(!verify_inclusion(&head, &head, &[]));
+    }
+
+    #[test]
+    fn verify_inclusion_with_accepts_keyed_and_domain_proofs() {
+        let mut keyed = Fragment::with_key(b"key".to_vec());
+        let mut domain = Fragment::new_with_domain(b"domain");
+        for frag in &mut [&mut keyed, &mut domain] {
+            frag.append_all(&[b"1", b"2", b"3"]).unwrap();
+        }
+        let cases: [(&Fragment, &[u8], &[u8]); 2] =
+            [(&keyed, b"key", b""), (&domain, b"", b"domain")];
+        for &(frag, key, domain) in &cases {
+            let head = frag.hash_of(frag.head.unwrap());
+            let root = frag.hash_of(*frag.ancestors(&head).last().unwrap());
+            let proof = owned_proof(frag, &root);
+            assert!(verify_inclusion_with(key, domain, &head, &root, &proof));
+            assert!(!verify_inclusion(&head, &root, &proof));
+            assert!(!verify_inclusion_with(b"other", domain, &head, &root, &proof));
+        }
+    }
+
+    #[test]
+    fn verify_inclusion_rejects_tampered_fact() {
+        let frag = chain(&[b"1", b"2", b"3"]);
+        let head = frag.head.unwrap().hash();
+        let root = frag.ancestors(&head).last().unwrap().hash();
+        let mut proof = owned_proof(&frag, &root);
+        if let OwnedEvent::Node { ref mut fact, .. } = proof[1] {
+            *fact = b"forged".to_vec();
+        }
+        assert!(!verify_inclusion(&head, &root, &proof));
+    }
+
+    #[test]
+    fn verify_inclusion_rejects_reordered_proof() {
+        let frag = chain(&[b"1", b"2", b"3"]);
+        let head = frag.head.unwrap().hash();
+        let root = frag.ancestors(&head).last().unwrap().hash();
+        let mut proof = owned_proof(&frag, &root);
+        proof.swap(1, 2);
+        assert!(!verify_inclusion(&head, &root, &proof));
+        proof.reverse();
+        assert!(!verify_inclusion(&head, &root, &proof));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn with_capacity_presizes_events() {
+        let mut frag = Fragment::with_capacity(100);
+        assert!(frag.events.capacity() >= 100);
+        assert!(frag.is_empty());
+        assert_eq!(frag.head, None);
+
+        frag.append(b"1").unwrap();
+        frag.append(b"2").unwrap();
+        assert_eq!(frag.depth(), 2);
+    }
+
+    #[test]
+    fn collect_matches_manual_appends() {
+        let facts: [&[u8]; 3] = [b"1", b"2", b"3"];
+        let collected: Fragment = facts.iter().cloned().collect();
+        let manual = chain(&facts);
+        assert_eq!(collected.head, manual.head);
+        assert_eq!(collected.depth(), 3);
+        assert_eq!(collected.events, manual.events);
+    }
+
+    #[test]
+    fn extend_appends_onto_existing_head() {
+        let mut frag = chain(&[b"1"]);
+        let more: [&[u8]; 2] = [b"2", b"3"];
+        frag.extend(more.iter().cloned());
+        assert_eq!(frag.head, chain(&[b"1", b"2", b"3"]).head);
+        assert_eq!(frag.depth(), 3);
+    }
+
+    #[test]
+    fn append_returns_hash_of_new_event() {
+        let mut frag = Fragment::new();
+        let root = frag.append(b"1").unwrap();
+        assert_eq!(root, Event::new(b"1", None).hash());
+        let prev_head = frag.head;
+        let node = frag.append(b"2").unwrap();
+        assert_eq!(node, Event::new(b"2", prev_head).hash());
+        assert_eq!(frag.head.unwrap().hash(), node);
+    }
+
+    #[test]
+    fn branches_diverge_from_shared_root() {
+        let mut frag = chain(&[b"main"]);
+        let head = frag.head;
+        let root = frag.append_to("left", b"root").unwrap();
+        assert_eq!(frag.append_to("right", b"root").unwrap(), root);
+        let left = frag.append_to("left", b"l").unwrap();
+        let right = frag.append_to("right", b"r").unwrap();
+        frag.append_to("left", b"l2").unwrap();
+
+        let left_tip = *frag.branch_head("left").unwrap();
+        let right_tip = *frag.branch_head("right").unwrap();
+        assert_eq!(right_tip.hash(), right);
+        assert_eq!(left_tip.parent(), Some(left));
+        assert_ne!(left_tip, right_tip);
+        assert_eq!(frag.common_ancestor(&left_tip.hash(), &right), Some(root));
+        assert_eq!(frag.head, head);
+    }
+
+    #[test]
+    fn new_branch_in_empty_fragment_starts_with_root() {
+        let mut frag = Fragment::new();
+        let hash = frag.append_to("other", b"1").unwrap();
+        assert_eq!(hash, Event::new(b"1", None).hash());
+        assert_eq!(frag.head, None);
+        assert_eq!(frag.branch_head("missing"), None);
+    }
+
+    #[test]
+    fn default_branch_is_head() {
+        let mut frag = chain(&[b"1"]);
+        assert_eq!(frag.branch_head(DEFAULT_BRANCH), frag.head.as_ref());
+        let hash = frag.append_to(DEFAULT_BRANCH, b"2").unwrap();
+        assert_eq!(frag.head.unwrap().hash(), hash);
+    }
+
+    #[test]
+    fn merge_keeps_deeper_branch_tip() {
+        let mut frag = Fragment::new();
+        frag.append_to("b", b"1").unwrap();
+        let mut other = frag.clone();
+        let deeper = other.append_to("b", b"2").unwrap();
+        let shallow = frag.branch_head("b").unwrap().hash();
+
+        let mut merged = other.clone();
+        merged.merge(frag.clone()).unwrap();
+        assert_eq!(merged.branch_head("b").unwrap().hash(), deeper);
+        frag.merge(other).unwrap();
+        assert_eq!(frag.branch_head("b").unwrap().hash(), deeper);
+        assert!(frag.is_ancestor_of(&shallow, &deeper));
+    }
+
+    #[test]
+    fn diff_of_identical_fragments_is_empty() {
+        let frag = chain(&[b"1", b"2"]);
+        let diff = frag.diff(&frag.clone());
+        assert_eq!(diff, FragmentDiff { only_self: vec![], only_other: vec![], common: 2 });
+    }
+
+    #[test]
+    fn diff_against_subset_lists_extra_events() {
+        let small = chain(&[b"1", b"2"]);
+        let mut big = small.clone();
+        let three = big.append(b"3").unwrap();
+        let four = big.append(b"4").unwrap();
+        let mut extra = vec![three, four];
+        extra.sort();
+
+        assert_eq!(big.diff(&small),
+                   FragmentDiff { only_self: extra.clone(), only_other: vec![], common: 2 });
+        assert_eq!(small.diff(&big),
+                   FragmentDiff { only_self: vec![], only_other: extra, common: 2 });
+    }
+
+    #[test]
+    fn diff_of_forks_lists_each_side() {
+        let base = chain(&[b"1"]);
+        let mut mine = base.clone();
+        let mut theirs = base.clone();
+        let my_hash = mine.append(b"mine").unwrap();
+        let their_hash = theirs.append(b"theirs").unwrap();
+
+        let diff = mine.diff(&theirs);
+        assert_eq!(diff.only_self, vec![my_hash]);
+        assert_eq!(diff.only_other, vec![their_hash]);
+        assert_eq!(diff.common, 1);
+    }
+
+    fn assert_parents_first(frag: &Fragment, order: &[Blake2]) {
+        assert_eq!(order.len(), frag.len());
+        for (i, hash) in order.iter().enumerate() {
+            for parent in frag.get(hash).unwrap().parents() {
+                if frag.contains(parent) {
+                    assert!(order[..i].contains(parent));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn topo_sort_of_linear_chain_is_insertion_order() {
+        let mut frag = Fragment::new();
+        let hashes = frag.append_all(&[b"1", b"2", b"3", b"4"]).unwrap();
+        assert_eq!(frag.topo_sort(), Ok(hashes));
+    }
+
+    #[test]
+    fn topo_sort_puts_fork_parent_before_both_children() {
+        let mut frag = chain(&[b"1"]);
+        let root = frag.head.unwrap();
+        let left = Event::new(b"left", Some(root));
+        let right = Event::new(b"right", Some(root));
+        frag.append_event(left).unwrap();
+        frag.append_event(right).unwrap();
+        let parents = [left.hash(), right.hash()];
+        frag.append_event(Event::new_merge(b"merge", &parents)).unwrap();
+        frag.append_event(Event::new(b"orphan", Some(Event::new(b"absent", None)))).unwrap();
+
+        let order = frag.topo_sort().unwrap();
+        assert_parents_first(&frag, &order);
+        let position = |hash: Blake2| order.iter().position(|&h| h == hash).unwrap();
+        assert!(position(root.hash()) < position(left.hash()));
+        assert!(position(root.hash()) < position(right.hash()));
+    }
+
+    #[test]
+    fn topo_sort_reports_cycle() {
+        let a = Event::new(b"a", None);
+        let b = Event::new(b"b", Some(a));
+        let mut frag = Fragment::new();
+        frag.events.insert(a.hash(), Event::new(b"a", Some(b)));
+        frag.events.insert(b.hash(), b);
+        let mut stuck = vec![a.hash(), b.hash()];
+        stuck.sort();
+        assert_eq!(frag.topo_sort(), Err(TopoSortError::Cycle(stuck)));
+    }
+
+    #[test]
+    fn gc_of_linear_chain_removes_nothing() {
+        let mut frag = chain(&[b"1", b"2", b"3"]);
+        assert_eq!(frag.gc(), 0);
+        assert_eq!(frag.len(), 3);
+    }
+
+    #[test]
+    fn gc_removes_abandoned_side_branch() {
+        let mut frag = chain(&[b"1", b"2"]);
+        let head = frag.head;
+        let root = frag.ancestors(&head.unwrap().hash()).last().cloned();
+        let side = Event::new(b"side", root);
+        frag.append_event(side).unwrap();
+        frag.append_event(Event::new(b"side 2", Some(side))).unwrap();
+        frag.head = head;
+
+        assert_eq!(frag.gc(), 2);
+        assert!(!frag.contains(&side.hash()));
+        assert_eq!(frag.timestamp(&side.hash()), None);
+        assert_eq!(frag.depth(), 2);
+        assert_eq!(frag.len(), 2);
+    }
+
+    #[test]
+    fn gc_drops_summaries_of_removed_events() {
+        let mut frag = chain(&[b"1"]);
+        let head = frag.head;
+        frag.append(b"side").unwrap();
+        let dropped = frag.checkpoint("count", b"2").unwrap();
+        frag.head = head;
+        let kept = frag.checkpoint("count", b"1").unwrap();
+        frag.add_summary(dropped);
+        frag.add_summary(kept);
+
+        assert_eq!(frag.gc(), 1);
+        assert_eq!(frag.summaries(), &[kept]);
+    }
+
+    #[test]
+    fn gc_keeps_every_branch_and_merge_parent() {
+        let mut frag = chain(&[b"1"]);
+        let root = frag.head.unwrap();
+        let side = frag.append_to("side", b"side").unwrap();
+        let other = Event::new(b"other", None);
+        frag.append_event(other).unwrap();
+        let parents = [root.hash(), other.hash()];
+        frag.append_event(Event::new_merge(b"merge", &parents)).unwrap();
+        frag.events.insert(Blake2::new(b"junk"), Event::new(b"junk", None));
+
+        assert_eq!(frag.gc(), 1);
+        assert!(frag.contains(&side));
+        assert!(frag.contains(&root.hash()));
+        assert!(frag.contains(&other.hash()));
+    }
+
+    #[test]
+    fn peek_does_not_advance_chain() {
+        let frag = chain(&[b"1", b"2"]);
+        let head = frag.head.unwrap();
+        let root = Event::new(b"1", None);
+        let mut chain = frag.summarize("test");
+
+        assert_eq!(chain.peek(), Some(&head));
+        assert_eq!(chain.peek(), Some(&head));
+        assert_eq!(chain.next_event(), Link::Event(head));
+        assert_eq!(chain.peek(), Some(&root));
+        assert_eq!(chain.next(), Some(root));
+        assert_eq!(chain.peek(), None);
+        assert_eq!(chain.next_event(), Link::Terminus(None));
+    }
+
+    #[test]
+    fn try_append_accepts_facts_up_to_limit() {
+        let mut frag = Fragment::new();
+        frag.set_max_fact_size(3);
+        assert_eq!(frag.try_append(b"ab"), Ok(Event::new(b"ab", None).hash()));
+        let head = frag.head;
+        assert_eq!(frag.try_append(b"abc"), Ok(Event::new(b"abc", head).hash()));
+        assert_eq!(frag.depth(), 2);
+    }
+
+    #[test]
+    fn try_append_rejects_facts_over_limit() {
+        let mut frag = chain(&[b"1"]);
+        let head = frag.head;
+        frag.set_max_fact_size(3);
+        assert_eq!(frag.try_append(b"abcd"),
+                   Err(AppendError::FactTooLarge { size: 4, limit: 3 }));
+        assert_eq!(frag.try_append_event(Event::new(b"abcd", head)),
+                   Err(AppendError::FactTooLarge { size: 4, limit: 3 }));
+        assert_eq!(frag.head, head);
+        assert_eq!(frag.len(), 1);
+    }
+
+    #[test]
+    fn zero_fact_size_limit_is_unlimited() {
+        let big = vec![0u8; 1 << 20];
+        let mut frag = Fragment::new();
+        assert!(frag.try_append(&big).is_ok());
+        frag.set_max_fact_size(1);
+        frag.set_max_fact_size(0);
+        assert!(frag.try_append(&big).is_ok());
+    }
+
+    #[test]
+    fn sealed_fragment_refuses_appends() {
+        let mut frag = Fragment::new();
+        assert_eq!(frag.try_append(b"1"), Ok(Event::new(b"1", None).hash()));
+        assert!(!frag.is_sealed());
+        frag.seal();
+        assert!(frag.is_sealed());
+
+        let before = frag.clone();
+        let next = Event::new(b"2", frag.head);
+        assert_eq!(frag.try_append(b"2"), Err(AppendError::Sealed));
+        assert_eq!(frag.try_append_event(next), Err(AppendError::Sealed));
+        assert_eq!(frag, before);
+        assert_eq!(frag.head, before.head);
+    }
+
+    #[test]
+    fn sealed_fragment_is_unchanged_by_every_method() {
+        let mut frag = chain(&[b"1", b"2", b"3"]);
+        let hashes: Vec<Blake2> = frag.replay().map(|event| event.hash()).collect();
+        frag.events.insert(Event::new(b"orphan", None).hash(), Event::new(b"orphan", None));
+        frag.seal();
+        let before = frag.clone();
+
+        assert_eq!(frag.merge(chain(&[b"1", b"2", b"3", b"4"])), Err(MergeError::Sealed));
+        assert_eq!(frag, before);
+        let source = chain(&[b"other"]);
+        let other = source.head.unwrap().hash();
+        assert_eq!(frag.graft(&source, &other), Err(GraftError::Sealed));
+        assert_eq!(frag, before);
+        assert_eq!(frag.prune(&hashes[1]), 0);
+        assert_eq!(frag, before);
+        assert_eq!(frag.gc(), 0);
+        assert_eq!(frag, before);
+        assert_eq!(frag.retain(|_| false), 0);
+        assert_eq!(frag, before);
+        assert!(frag.is_sealed());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn seal_survives_serde() {
+        let mut frag = chain(&[b"1"]);
+        frag.seal();
+        let json = ::serde_json::to_string(&frag).unwrap();
+        let decoded: Fragment = ::serde_json::from_str(&json).unwrap();
+        assert!(decoded.is_sealed());
+    }
+
+    #[test]
+    fn append_to_sealed_fragment_fails() {
+        let mut frag = chain(&[b"1"]);
+        frag.seal();
+        let (len, head) = (frag.len(), frag.head);
+        let next = Event::new(b"2", frag.head);
+        assert_eq!(frag.append_event(next), Err(AppendError::Sealed));
+        assert_eq!(frag.append(b"2"), Err(AppendError::Sealed));
+        assert_eq!(frag.append_all(&[b"2", b"3"]), Err(AppendError::Sealed));
+        assert_eq!(frag.append_to("side", b"2"), Err(AppendError::Sealed));
+        assert_eq!(frag.append_event_dedup(next), Err(AppendError::Sealed));
+        assert_eq!(frag.len(), len);
+        assert_eq!(frag.head, head);
+        assert_eq!(frag.branch_head("side"), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "sealed")]
+    fn extending_sealed_fragment_panics() {
+        let mut frag = chain(&[b"1"]);
+        frag.seal();
+        frag.extend(vec![&b"2"[..]]);
+    }
+
+    #[test]
+    fn collect_events_returns_only_stored_wanted_events() {
+        let mut frag = Fragment::new();
+        let hashes = frag.append_all(&[b"1", b"2", b"3"]).unwrap();
+        let absent = Blake2::new(b"absent");
+        let wanted = [hashes[2], absent, hashes[0]];
+        assert_eq!(frag.collect_events(&wanted),
+                   vec![frag.events[&hashes[2]], frag.events[&hashes[0]]]);
+        assert!(frag.collect_events(&[]).is_empty());
+    }
+
+    #[test]
+    fn last_terminus_of_complete_chain_is_none() {
+        let frag = chain(&[b"1", b"2"]);
+        let mut chain = frag.summarize("test");
+        assert_eq!(chain.last_terminus(), None);
+        assert_eq!(chain.by_ref().count(), 2);
+        assert_eq!(chain.last_terminus(), None);
+    }
+
+    #[test]
+    fn last_terminus_of_broken_chain_is_missing_hash() {
+        let mut frag = chain(&[b"1", b"2", b"3"]);
+        let middle = frag.head.unwrap().parent().unwrap();
+        frag.events.remove(&middle);
+
+        let mut chain = frag.summarize("test");
+        assert_eq!(chain.by_ref().count(), 1);
+        assert_eq!(chain.last_terminus(), Some(middle));
+    }
+
+    #[test]
+    fn blake2s_fragment_builds_traverses_and_verifies() {
+        let mut frag: Fragment<Blake2s> = Fragment::default();
+        let hashes = frag.append_all(&[b"1", b"2", b"3"]).unwrap();
+        assert!(hashes.iter().all(|hash| hash.bytes.len() == 32));
+
+        let root: Event<Blake2s> = Event::with_parent(b"1", None);
+        let len = 1u64.to_be_bytes();
+        let domain_len = 0u64.to_be_bytes();
+        assert_eq!(hashes[0], Blake2s::new_concat(&[&domain_len, &[0], &len, b"1"]));
+        assert_eq!(hashes[1], Event::with_parent(b"2", Some(root)).hash());
+        assert_eq!(hashes[1],
+                   Blake2s::new_concat(&[&domain_len, &[1], &len, b"2", &hashes[0].bytes]));
+
+        assert_eq!(frag.verify(), Ok(()));
+        assert_eq!(frag.depth(), 3);
+        let facts: Vec<&[u8]> = frag.summarize("test").facts().collect();
+        assert_eq!(facts, vec![&b"3"[..], b"2", b"1"]);
+    }
+
+    #[test]
+    fn single_chain_has_one_root() {
+        let frag = chain(&[b"1", b"2", b"3"]);
+        assert_eq!(frag.roots(), vec![Event::new(b"1", None).hash()]);
+    }
+
+    #[test]
+    fn merged_disjoint_chains_have_two_roots() {
+        let mut frag = chain(&[b"1", b"2"]);
+        frag.merge(chain(&[b"a", b"b"])).unwrap();
+        let mut roots = frag.roots();
+        roots.sort();
+        let mut expected = vec![Event::new(b"1", None).hash(), Event::new(b"a", None).hash()];
+        expected.sort();
+        assert_eq!(roots, expected);
+    }
+
+    #[test]
+    fn empty_fragment_has_no_roots() {
+        assert!(Fragment::new().roots().is_empty());
+    }
+
+    #[test]
+    fn checkpoint_points_at_head() {
+        let frag = chain(&[b"1", b"2"]);
+        let summary = frag.checkpoint("count", b"2").unwrap();
+        assert_eq!(summary.event(), frag.head.unwrap().hash());
+        assert_eq!(summary.name(), "count");
+        assert_eq!(summary.summary(), b"2");
+        assert!(summary.verify_reachable_from_head(&frag));
+    }
+
+    #[test]
+    fn checkpoint_of_empty_fragment_is_none() {
+        assert_eq!(Fragment::new().checkpoint("count", b"0"), None);
+    }
+
+    #[test]
+    fn replay_runs_from_root_to_head() {
+        let frag = chain(&[b"1", b"2", b"3"]);
+        let replayed: Vec<Event> = frag.replay().collect();
+
+        let mut chain = frag.summarize("test");
+        let mut walked = Vec::new();
+        while let Link::Event(event) = chain.next_event() {
+            walked.push(event);
+        }
+        walked.reverse();
+        assert_eq!(replayed, walked);
+        assert_eq!(replayed[0], Event::new(b"1", None));
+        assert_eq!(frag.replay().len(), 3);
+        assert_eq!(Fragment::new().replay().next(), None);
+    }
+
+    #[test]
+    fn fragment_equals_its_clone() {
+        let frag = chain(&[b"1", b"2"]);
+        assert_eq!(frag, frag.clone());
+        assert_eq!(Fragment::new(), Fragment::new());
+    }
+
+    #[test]
+    fn fragments_from_same_facts_are_equal() {
+        let facts: Vec<Vec<u8>> = vec![b"1".to_vec(), b"2".to_vec()];
+        let borrowed: Vec<&[u8]> = facts.iter().map(|fact| &fact[..]).collect();
+        let a = chain(&borrowed);
+        let b = chain(&[b"1", b"2"]);
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn fragments_differing_by_one_event_are_unequal() {
+        let a = chain(&[b"1", b"2"]);
+        let mut b = a.clone();
+        let head = b.head;
+        b.append_event(Event::new(b"side", None)).unwrap();
+        b.head = head;
+        assert_ne!(a, b);
+        assert_ne!(b, a);
+
+        let mut c = a.clone();
+        c.head = None;
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn bloom_holds_every_event() {
+        let mut frag = Fragment::new();
+        let hashes = frag.append_all(&[b"1", b"2", b"3"]).unwrap();
+        let bloom = frag.bloom(256, 4);
+        assert!(hashes.iter().all(|hash| bloom.might_contain(hash)));
+        assert!(!Fragment::new().bloom(256, 4).might_contain(&hashes[0]));
+    }
+
+    #[test]
+    fn cloned_chain_continues_independently() {
+        let frag = chain(&[b"1", b"2", b"3"]);
+        let mut chain = frag.summarize("test");
+        chain.next_event();
+
+        let mut fork = chain.clone();
+        assert_eq!(fork.next(), Some(Event::new(b"2", Some(Event::new(b"1", None)))));
+        assert_eq!(fork.next(), Some(Event::new(b"1", None)));
+        assert_eq!(fork.next_event(), Link::Terminus(None));
+
+        assert_eq!(chain.count(), 2);
+    }
+
+    #[test]
+    fn fragment_can_grow_after_walk() {
+        let mut frag = chain(&[b"1", b"2"]);
+        let walked: Vec<Event> = frag.summarize("test").collect();
+        assert_eq!(walked.len(), 2);
+
+        frag.append(b"3").unwrap();
+        assert_eq!(frag.summarize("test").count(), 3);
+        assert_eq!(walked[0], Event::new(b"2", Some(walked[1])));
+    }
+
+    #[test]
+    fn resolve_prefix_finds_unique_match() {
+        let frag = chain(&[b"1", b"2", b"3"]);
+        let head = frag.head.unwrap().hash();
+        let prefix = head.short_hex(12).to_uppercase();
+        assert_eq!(frag.resolve_prefix(&prefix), Ok(head));
+        assert_eq!(frag.resolve_prefix(&head.to_hex()), Ok(head));
+    }
+
+    #[test]
+    fn resolve_prefix_reports_ambiguity() {
+        // Seventeen hashes must share a first hex digit.
+        let facts: Vec<Vec<u8>> = (0..17).map(|i| vec![i]).collect();
+        let frag: Fragment = facts.iter().map(|fact| &fact[..]).collect();
+        let digit = (0..16)
+            .map(|d| format!("{:x}", d))
+            .find(|d| frag.events.keys().filter(|hash| hash.to_hex().starts_with(d)).count() > 1)
+            .unwrap();
+        let mut sharing: Vec<Blake2> = frag.events.keys()
+            .filter(|hash| hash.to_hex().starts_with(&digit))
+            .cloned()
+            .collect();
+        sharing.sort();
+        assert_eq!(frag.resolve_prefix(&digit), Err(ResolveError::Ambiguous(sharing)));
+    }
+
+    #[test]
+    fn resolve_prefix_reports_no_match() {
+        let frag = chain(&[b"1", b"2"]);
+        let absent = Event::new(b"3", frag.head).hash();
+        assert_eq!(frag.resolve_prefix(&absent.to_hex()), Err(ResolveError::NotFound));
+        assert_eq!(frag.resolve_prefix("not hex"), Err(ResolveError::NotFound));
+        assert_eq!(Fragment::new().resolve_prefix(""), Err(ResolveError::NotFound));
+    }
+
+    #[test]
+    fn append_event_dedup_ignores_known_events() {
+        let mut frag = chain(&[b"1", b"2"]);
+        let root = Event::new(b"1", None);
+        let head = frag.head;
+
+        assert!(!frag.append_event_dedup(root).unwrap());
+        assert_eq!(frag.len(), 2);
+        assert_eq!(frag.head, head);
+
+        let tip = Event::new(b"3", head);
+        assert!(frag.append_event_dedup(tip).unwrap());
+        assert!(!frag.append_event_dedup(tip).unwrap());
+        assert_eq!(frag.len(), 3);
+        assert_eq!(frag.head, Some(tip));
+    }
+
+    #[test]
+    fn domains_separate_identical_facts() {
+        let mut ours = Fragment::new_with_domain(b"ours");
+        let mut theirs = Fragment::new_with_domain(b"theirs");
+        let our_hashes = ours.append_all(&[b"1", b"2"]).unwrap();
+        let their_hashes = theirs.append_all(&[b"1", b"2"]).unwrap();
+        assert!(our_hashes.iter().all(|hash| !their_hashes.contains(hash)));
+        assert_ne!(ours, theirs);
+
+        let plain = chain(&[b"1", b"2"]);
+        assert!(plain.events.keys().all(|hash| !our_hashes.contains(hash)));
+        assert_eq!(Fragment::new_with_domain(b"").append(b"1").unwrap(),
+                   Event::new(b"1", None).hash());
+    }
+
+    #[test]
+    fn domain_fragment_is_consistent() {
+        let mut frag = Fragment::new_with_domain(b"ours");
+        let root = frag.append(b"1").unwrap();
+        let head = frag.append(b"2").unwrap();
+        frag.append_to("side", b"3").unwrap();
+
+        assert_eq!(frag.verify(), Ok(()));
+        assert_eq!(frag.depth(), 2);
+        assert_eq!(frag.hash_of(frag.head.unwrap()), head);
+        assert_eq!(frag.head.unwrap().parent(), Some(root));
+        assert_eq!(frag.summarize("test").count(), 2);
+        assert!(frag.checkpoint("count", b"2").unwrap().verify_reachable_from_head(&frag));
+        assert_eq!(frag.inclusion_proof(&root).map(|proof| proof.len()), Some(2));
+        assert_eq!(frag.gc(), 0);
+        assert!(!frag.append_event_dedup(frag.events[&root]).unwrap());
+    }
+
+    #[test]
+    fn latest_summary_is_deepest() {
+        let mut frag = chain(&[b"1", b"2", b"3"]);
+        let head = frag.head.unwrap().hash();
+        let root = Event::new(b"1", None).hash();
+        assert_eq!(frag.latest_summary(), None);
+
+        frag.add_summary(Summary::new("count", b"3", head));
+        frag.add_summary(Summary::new("count", b"1", root));
+        frag.append(b"4").unwrap();
+
+        assert_eq!(frag.latest_summary().map(|summary| summary.event()), Some(head));
+        assert_eq!(frag.summaries().len(), 2);
+        assert!(frag.summaries().iter().all(|summary| summary.is_valid_for(&frag)));
+    }
+
+    #[test]
+    fn take_events_stops_at_limit() {
+        let facts: Vec<Vec<u8>> = (0..10).map(|i| vec![i]).collect();
+        let frag: Fragment = facts.iter().map(|fact| &fact[..]).collect();
+
+        let page: Vec<&[u8]> = frag.summarize("test").take_events(3)
+            .map(|event| event.fact())
+            .collect();
+        assert_eq!(page, [&[9][..], &[8], &[7]]);
+        assert_eq!(frag.summarize("test").take_events(20).count(), 10);
+    }
+
+    #[test]
+    fn graft_copies_event_and_ancestors() {
+        let source = chain(&[b"1", b"2", b"3", b"4"]);
+        let third = Event::new(b"3", Some(Event::new(b"2", Some(Event::new(b"1", None)))));
+
+        let mut frag = Fragment::new();
+        assert_eq!(frag.graft(&source, &third.hash()), Ok(3));
+        assert_eq!(frag.len(), 3);
+        assert_eq!(frag.head, None);
+        assert!(frag.orphans().is_empty());
+        assert_eq!(frag.timestamp(&third.hash()), source.timestamp(&third.hash()));
+    }
+
+    #[test]
+    fn graft_of_present_event_changes_nothing() {
+        let source = chain(&[b"1", b"2"]);
+        let mut frag = source.clone();
+        let head = source.head.unwrap().hash();
+        assert_eq!(frag.graft(&source, &head), Ok(0));
+        assert_eq!(frag, source);
+    }
+
+    #[test]
+    fn graft_fails_on_missing_history() {
+        let mut source = chain(&[b"1", b"2", b"3"]);
+        let root = Event::new(b"1", None).hash();
+        source.events.remove(&root);
+        let head = source.head.unwrap().hash();
+
+        let mut frag = Fragment::new();
+        assert_eq!(frag.graft(&source, &head), Err(GraftError::NotFound(root)));
+        assert_eq!(frag.graft(&source, &root), Err(GraftError::NotFound(root)));
+        assert!(frag.is_empty());
+    }
+
+    #[test]
+    fn fingerprint_depends_only_on_event_set() {
+        assert_eq!(Fragment::new().fingerprint(), None);
+
+        let mut one = chain(&[b"1", b"2"]);
+        let mut other = Fragment::new();
+        other.append_event(Event::new(b"2", Some(Event::new(b"1", None)))).unwrap();
+        other.append_event(Event::new(b"1", None)).unwrap();
+        assert!(one.fingerprint().is_some());
+        assert_eq!(one.fingerprint(), other.fingerprint());
+
+        let before = one.fingerprint();
+        one.append(b"3").unwrap();
+        assert_ne!(one.fingerprint(), before);
+    }
+
+    #[test]
+    fn filter_facts_keeps_matches_in_order() {
+        let frag = chain(&[b"TX:1", b"NOTE:2", b"TX:3", b"TX:4", b"NOTE:5"]);
+        let txs: Vec<&[u8]> = frag.summarize("test")
+            .filter_facts(|fact| fact.starts_with(b"TX:"))
+            .map(|event| event.fact())
+            .collect();
+        assert_eq!(txs, [&b"TX:4"[..], b"TX:3", b"TX:1"]);
+    }
+
+    #[test]
+    fn chain_builder_matches_appends() {
+        let built = EventChainBuilder::new().root(b"1").then(b"2").then(b"3").build();
+        assert_eq!(built, chain(&[b"1", b"2", b"3"]));
+        assert_eq!(EventChainBuilder::new().then(b"1").build(), chain(&[b"1"]));
+
+        let two_roots = EventChainBuilder::new().root(b"1").then(b"2").root(b"a").build();
+        assert_eq!(two_roots.roots().len(), 2);
+        assert_eq!(two_roots.depth(), 1);
+    }
+
+    #[test]
+    fn to_dot_declares_events_and_parent_links() {
+        let mut frag = chain(&[b"root", b"\"quoted\""]);
+        let side = Event::new(b"side", None);
+        frag.append_event(side).unwrap();
+        let parents = [frag.head.unwrap().hash(), side.hash()];
+        frag.append_event(Event::new_merge(b"merge", &parents)).unwrap();
+
+        let dot = frag.to_dot();
+        assert!(dot.starts_with("digraph fragment {\n") && dot.ends_with("}\n"));
+        assert_eq!(dot.matches("[label=").count(), 4);
+        assert_eq!(dot.matches(" -> ").count(), 3);
+        assert_eq!(dot.matches("shape=box").count(), 2);
+        assert!(dot.contains("\\\"quoted\\\""));
+    }
+
+    #[test]
+    fn to_dot_labels_short_hashes_in_full() {
+        let mut frag = Fragment::<Blake2b<2>>::default();
+        let hash = frag.append(b"root").unwrap();
+        assert!(frag.to_dot().contains(&format!("[label=\"{}\\nroot\"", hash.to_hex())));
+    }
+
+    #[test]
+    fn keys_separate_identical_facts() {
+        let mut ours = Fragment::with_key(b"ours".to_vec());
+        let mut theirs = Fragment::with_key(b"theirs".to_vec());
+        let our_hashes = ours.append_all(&[b"1", b"2", b"3"]).unwrap();
+        let their_hashes = theirs.append_all(&[b"1", b"2", b"3"]).unwrap();
+        let plain = chain(&[b"1", b"2", b"3"]);
+        assert!(our_hashes.iter().all(|hash| !their_hashes.contains(hash)));
+        assert!(our_hashes.iter().all(|hash| !plain.contains(hash)));
+
+        for frag in &[&ours, &theirs] {
+            assert_eq!(frag.verify(), Ok(()));
+            assert_eq!(frag.summarize("test").facts().collect::<Vec<_>>(), [&b"3"[..], b"2", b"1"]);
+        }
+        let root = Event::new(b"1", None);
+        let len = 1u64.to_be_bytes();
+        let expected = Blake2::new_concat_keyed(b"ours", &[&[0; 8], &[0], &len, b"1"]);
+        assert_eq!(ours.hash_of(root), expected);
+    }
+
+    #[test]
+    #[should_panic]
+    fn overlong_key_is_refused() {
+        Fragment::with_key(vec![0; 65]);
+    }
+
+    #[test]
+    fn tip_facts_are_newest_first() {
+        let frag = chain(&[b"one", b"two", b"three", b"four", b"five"]);
+        assert_eq!(frag.tip_facts(2), vec![b"five".to_vec(), b"four".to_vec()]);
+        assert_eq!(frag.tip_facts(10).len(), 5);
+        assert!(Fragment::new().tip_facts(2).is_empty());
+    }
+
+    #[test]
+    fn debug_is_the_same_for_equal_fragments() {
+        let mut a = Fragment::new();
+        a.append_all(&[b"one", b"two"]).unwrap();
+        a.append_event(Event::new(b"side", None)).unwrap();
+        let mut b = Fragment::new();
+        b.append_event(Event::new(b"side", None)).unwrap();
+        b.merge(a.clone()).unwrap();
+
+        assert_eq!(a, b);
+        assert_eq!(format!("{:?}", a), format!("{:?}", b));
+        assert_eq!(format!("{:?}", Fragment::new()),
+                   "Fragment { head: None, len: 0, depth: 0, events: {} }");
+    }
+
+    #[test]
+    fn debug_shows_short_hashes_in_full() {
+        let mut frag = Fragment::<Blake2b<2>>::default();
+        let hash = frag.append(b"one").unwrap().to_hex();
+        assert_eq!(format!("{:?}", frag),
+                   format!("Fragment {{ head: Some({0}), len: 1, depth: 1, \
+                            events: {{{0}: Root {{ fact: \"one\" }}}} }}", hash));
+    }
+
+    #[test]
+    fn chain_is_linear() {
+        assert!(chain(&[b"1", b"2", b"3"]).is_linear());
+    }
+
+    #[test]
+    fn single_root_and_empty_fragment_are_linear() {
+        assert!(chain(&[b"1"]).is_linear());
+        assert!(Fragment::new().is_linear());
+    }
+
+    #[test]
+    fn forked_fragment_is_not_linear() {
+        let mut frag = chain(&[b"1", b"2"]);
+        let root = Event::new(b"1", None);
+        frag.append_event(Event::new(b"fork", Some(root))).unwrap();
+        assert!(!frag.is_linear());
+    }
+
+    #[test]
+    fn merged_or_broken_fragment_is_not_linear() {
+        let mut frag = chain(&[b"1"]);
+        let side = Event::new(b"side", None);
+        frag.append_event(side).unwrap();
+        let parents = [Event::new(b"1", None).hash(), side.hash()];
+        frag.append_event(Event::new_merge(b"merge", &parents)).unwrap();
+        assert!(!frag.is_linear());
+
+        let mut broken = chain(&[b"1", b"2", b"3"]);
+        broken.events.remove(&Event::new(b"1", None).hash());
+        assert!(!broken.is_linear());
+    }
+
+    #[test]
+    fn append_str_appends_the_same_event_as_append() {
+        let mut by_str = Fragment::new();
+        let mut by_bytes = Fragment::new();
+        assert_eq!(by_str.append_str("hello").unwrap(), by_bytes.append(b"hello").unwrap());
+        assert_eq!(by_str.append_str("world").unwrap(), by_bytes.append(b"world").unwrap());
+        assert_eq!(by_str, by_bytes);
+    }
+
+    #[test]
+    fn stats_count_each_kind_of_event() {
+        let mut frag = chain(&[b"one", b"two", b"three"]);
+        let head = frag.head;
+        let absent = Event::new(b"absent", None);
+        frag.append_event(Event::new(b"side", None)).unwrap();
+        frag.append_event(Event::new(b"orphan", Some(absent))).unwrap();
+        frag.head = head;
+
+        assert_eq!(frag.stats(), FragmentStats {
+            total_events: 5,
+            depth: 3,
+            root_count: 2,
+            leaf_count: 3,
+            orphan_count: 1,
+            total_fact_bytes: 3 + 3 + 5 + 4 + 6,
+        });
+        assert_eq!(Fragment::new().stats(), FragmentStats::default());
+    }
+
+    #[test]
+    fn on_append_hooks_see_every_event_in_order() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut frag = Fragment::new();
+        for name in &["first", "second"] {
+            let seen = seen.clone();
+            frag.on_append(Box::new(move |event, &hash| {
+                assert_eq!(event.hash(), hash);
+                seen.lock().unwrap().push((*name, hash));
+            }));
+        }
+
+        let one = frag.append(b"one").unwrap();
+        let side = Event::new(b"side", None);
+        frag.append_event(side).unwrap();
+        assert_eq!(*seen.lock().unwrap(), vec![("first", one), ("second", one),
+                                               ("first", side.hash()), ("second", side.hash())]);
+
+        seen.lock().unwrap().clear();
+        let mut copy = frag.clone();
+        copy.append(b"unseen").unwrap();
+        assert!(seen.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn on_append_hooks_get_the_stored_hash() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut frag = Fragment::with_key(b"secret".to_vec());
+        let recorder = seen.clone();
+        frag.on_append(Box::new(move |_, &hash| recorder.lock().unwrap().push(hash)));
+        let hash = frag.append(b"one").unwrap();
+        assert_ne!(hash, Event::new(b"one", None).hash());
+        assert_eq!(*seen.lock().unwrap(), vec![hash]);
+    }
+
+    #[test]
+    fn slice_takes_the_events_between_two_hashes() {
+        let mut frag = Fragment::new();
+        let hashes = frag.append_all(&[b"1", b"2", b"3", b"4", b"5"]).unwrap();
+        let facts = |slice: Vec<Event>| -> Vec<Vec<u8>> {
+            slice.iter().map(|event| event.fact().to_vec()).collect()
+        };
+
+        let mid = frag.slice(&hashes[1], &hashes[3]).unwrap();
+        assert_eq!(facts(mid), vec![b"4".to_vec(), b"3".to_vec(), b"2".to_vec()]);
+
+        let whole = frag.slice(&hashes[0], &hashes[4]).unwrap();
+        assert_eq!(whole, frag.summarize("test").collect::<Vec<_>>());
+
+        let one = frag.slice(&hashes[2], &hashes[2]).unwrap();
+        assert_eq!(facts(one), vec![b"3".to_vec()]);
+    }
+
+    #[test]
+    fn slice_fails_off_the_lineage() {
+        let mut frag = Fragment::new();
+        let hashes = frag.append_all(&[b"1", b"2", b"3"]).unwrap();
+        let side = frag.append_to("side", b"side").unwrap();
+
+        assert_eq!(frag.slice(&hashes[2], &hashes[0]), Err(SliceError::NotAncestor));
+        assert_eq!(frag.slice(&side, &hashes[2]), Err(SliceError::NotAncestor));
+        assert_eq!(frag.slice(&hashes[0], &side), Err(SliceError::NotAncestor));
+        let missing = Event::new(b"missing", None).hash();
+        assert_eq!(frag.slice(&hashes[0], &missing), Err(SliceError::NotFound(missing)));
+    }
+
+    #[test]
+    fn total_bytes_sums_every_event() {
+        let mut frag = Fragment::new();
+        assert_eq!(frag.total_bytes(), 0);
+        let hashes = frag.append_all(&[b"one", b"two", b"three"]).unwrap();
+        assert_eq!(frag.total_bytes(), 3 + (3 + 64) + (5 + 64));
+
+        let side = Event::new(b"side", None);
+        frag.append_event(side).unwrap();
+        let parents = [hashes[2], side.hash()];
+        frag.append_event(Event::new_merge(b"merge", &parents)).unwrap();
+        assert_eq!(frag.total_bytes(), 3 + 67 + 69 + 4 + (5 + 128));
+    }
+
+    #[test]
+    fn owned_fragment_with_unstored_head_has_no_head() {
+        let owned = OwnedFragment {
+            head: Some(Event::new(b"missing", None).hash()),
+            events: Default::default(),
+        };
+        assert_eq!(owned.as_fragment().head, None);
+    }
+
+    #[test]
+    fn append_cached_reuses_computed_hash() {
+        let root: CachedEvent<CountingHash> = CachedEvent::new(Event::with_parent(b"1", None));
+        let hash = root.hash();
+        let before = CountingHash::computed();
+        let mut frag = Fragment::default();
+        frag.append_cached(&root).unwrap();
+        assert_eq!(CountingHash::computed(), before);
+        assert_eq!(frag.get(&hash), Some(&root.event()));
+        assert_eq!(frag.head, Some(root.event()));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn fragment_round_trips_through_json() {
+        let mut frag = Fragment::new();
+        frag.append(b"one").unwrap();
+        frag.append(b"two").unwrap();
+
+        let json = ::serde_json::to_string(&frag).unwrap();
+        let decoded: Fragment = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.head, frag.head);
+        assert_eq!(decoded.events, frag.events);
+        assert!(json.contains(&frag.head.unwrap().hash().to_hex()));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn domain_round_trips_through_json() {
+        let mut frag = Fragment::new_with_domain(b"ours");
+        frag.append(b"one").unwrap();
+
+        let json = ::serde_json::to_string(&frag).unwrap();
+        let mut decoded: Fragment = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.domain(), b"ours");
+        assert_eq!(decoded.append(b"two").unwrap(), frag.append(b"two").unwrap());
+    }
 }
@@ -1,63 +1,778 @@
-use std::fmt::{Debug, Formatter, Error};
-use std::fmt::Write;
-use std::hash::{Hash, Hasher};
+use core::convert::TryFrom;
+use core::fmt::{Debug, Display, Formatter, Error};
+use core::fmt::Write;
+use core::cmp::Ordering;
+use core::hash::{Hash, Hasher};
+#[cfg(feature = "std")]
+use std::io;
 
-use blake2_rfc::blake2b::blake2b;
+use blake2_rfc::blake2b::{self, blake2b};
+use blake2_rfc::blake2s;
+#[cfg(not(feature = "std"))]
+use prelude::*;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+#[cfg(feature = "serde")]
+use serde::de::{self, Unexpected, Visitor};
 
-pub struct Blake2 {
-    pub bytes: [u8; 64]
+/// `N`-byte blake2b hash of a byte string. `N` must be between 1 and 64.
+pub struct Blake2b<const N: usize> {
+    pub bytes: [u8; N]
 }
 
-/// 64-byte blake2b hash of a byte string
-impl Blake2 {
-    pub fn new(obj: &[u8]) -> Blake2 {
-        Blake2 {  bytes: into_64bytes(blake2b(64, b"a key", obj).as_bytes()) }
+/// The default, 64-byte hash used throughout Pender.
+pub type Blake2 = Blake2b<64>;
+
+/// A hash function that identifies Events: `Blake2` by default, or
+/// `Blake2s` where 64-byte hashes are too big.
+///
+/// Events, Fragments and Summaries take the hash type as a parameter, so
+/// everything in one Fragment is hashed the same way.
+pub trait EventHash: Copy + Eq + Ord + Hash + Debug + AsRef<[u8]> {
+    /// Hash of the concatenation of `parts` under `key` (empty for an
+    /// unkeyed hash).
+    fn hash_parts_keyed(key: &[u8], parts: &[&[u8]]) -> Self;
+
+    /// Unkeyed hash of the concatenation of `parts`.
+    fn hash_parts(parts: &[&[u8]]) -> Self {
+        Self::hash_parts_keyed(b"", parts)
+    }
+
+    /// Lowercase hex encoding of the hash.
+    fn to_hex(&self) -> String {
+        to_hex(self.as_ref())
+    }
+
+    /// The first `n` characters of `to_hex` (all of them, if `n` is larger).
+    fn short_hex(&self, n: usize) -> String {
+        let mut hex = self.to_hex();
+        hex.truncate(n);
+        hex
+    }
+}
+
+impl<const N: usize> EventHash for Blake2b<N> {
+    fn hash_parts_keyed(key: &[u8], parts: &[&[u8]]) -> Blake2b<N> {
+        Blake2b::new_concat_keyed(key, parts)
     }
 }
 
-impl Copy for Blake2 { }
+impl<const N: usize> Blake2b<N> {
+    /// Unkeyed hash of `obj`; equivalent to `new_keyed(b"", obj)`.
+    ///
+    /// Earlier versions hashed everything under the fixed key `b"a key"`, so
+    /// hashes (and therefore event identities) produced by those versions
+    /// do not match the ones produced here.
+    pub fn new(obj: &[u8]) -> Blake2b<N> {
+        Blake2b::new_keyed(b"", obj)
+    }
+
+    /// Keyed hash of `obj`. The key may be up to 64 bytes long.
+    pub fn new_keyed(key: &[u8], obj: &[u8]) -> Blake2b<N> {
+        let digest = blake2b(N, key, obj);
+        let bytes = into_bytes(digest.as_bytes())
+            .expect("blake2b digest has the requested length");
+        Blake2b {  bytes: bytes }
+    }
+
+    /// Unkeyed hash of the concatenation of `parts`, computed without
+    /// building the concatenation.
+    pub fn new_concat(parts: &[&[u8]]) -> Blake2b<N> {
+        Blake2b::new_concat_keyed(b"", parts)
+    }
 
-impl Clone for Blake2 {
-    fn clone(&self) -> Blake2 {
+    /// Like `new_concat`, under `key` (see `new_keyed`).
+    pub fn new_concat_keyed(key: &[u8], parts: &[&[u8]]) -> Blake2b<N> {
+        let mut hasher = Blake2bHasher::new(key);
+        for part in parts {
+            hasher.update(part);
+        }
+        hasher.finalize()
+    }
+
+    /// Compare two hashes in time that doesn't depend on where they differ.
+    ///
+    /// `==` stops at the first differing byte, so its timing reveals how
+    /// many leading bytes matched. Use `ct_eq` when checking a hash supplied
+    /// by an untrusted party against a secret or keyed one; `==` is fine
+    /// everywhere else.
+    pub fn ct_eq(&self, other: &Blake2b<N>) -> bool {
+        self.bytes.iter()
+            .zip(other.bytes.iter())
+            .fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+    }
+
+    /// The all-zero hash: the identity for `xor`.
+    pub fn zero() -> Blake2b<N> {
+        Blake2b { bytes: [0; N] }
+    }
+
+    /// Byte-wise XOR of two hashes. XOR is commutative and associative, so
+    /// folding a set of hashes with it gives the same value in any order.
+    ///
+    /// ```
+    /// use pender::hash::Blake2;
+    ///
+    /// let hashes = [Blake2::new(b"one"), Blake2::new(b"two"), Blake2::new(b"three")];
+    /// let forward = hashes.iter().fold(Blake2::zero(), |acc, h| acc.xor(h));
+    /// let backward = hashes.iter().rev().fold(Blake2::zero(), |acc, h| acc.xor(h));
+    /// assert_eq!(forward, backward);
+    /// ```
+    pub fn xor(&self, other: &Blake2b<N>) -> Blake2b<N> {
+        let mut bytes = self.bytes;
+        for (x, y) in bytes.iter_mut().zip(other.bytes.iter()) {
+            *x ^= *y;
+        }
+        Blake2b { bytes: bytes }
+    }
+
+    /// Lowercase hex encoding of the hash (128 characters for a `Blake2`).
+    pub fn to_hex(&self) -> String {
+        to_hex(&self.bytes)
+    }
+
+    /// The first `n` characters of `to_hex` (all of them, if `n` is larger).
+    pub fn short_hex(&self, n: usize) -> String {
+        let mut hex = self.to_hex();
+        hex.truncate(n);
+        hex
+    }
+
+    /// Parse a hash from its hex encoding (either case), which must be
+    /// exactly two characters per byte.
+    ///
+    /// ```
+    /// use pender::hash::{Blake2, HashParseError};
+    ///
+    /// let hash = Blake2::new(b"potato");
+    /// assert_eq!(Blake2::from_hex(&hash.to_hex()), Ok(hash));
+    /// assert_eq!(Blake2::from_hex("abc"), Err(HashParseError::BadLength(3)));
+    /// ```
+    pub fn from_hex(s: &str) -> Result<Blake2b<N>, HashParseError> {
+        Ok(Blake2b { bytes: from_hex(s)? })
+    }
+}
+
+/// Incremental form of `Blake2b::new_keyed`, for data too large to hold in
+/// memory at once. Feeding it the data in any number of chunks gives the
+/// same hash as the one-shot constructor.
+///
+/// ```
+/// use pender::hash::{Blake2, Blake2Hasher};
+///
+/// let mut hasher = Blake2Hasher::new(b"");
+/// hasher.update(b"pot");
+/// hasher.update(b"ato");
+/// assert_eq!(hasher.finalize(), Blake2::new(b"potato"));
+/// ```
+///
+/// With the `std` feature it is also an `io::Write`, so a file can be
+/// hashed with `io::copy`.
+#[derive(Clone, Debug)]
+pub struct Blake2bHasher<const N: usize> {
+    state: blake2b::Blake2b,
+}
+
+/// Streaming hasher for the default, 64-byte `Blake2`.
+pub type Blake2Hasher = Blake2bHasher<64>;
+
+impl<const N: usize> Blake2bHasher<N> {
+    /// Start a hash under `key`, which may be up to 64 bytes long (empty
+    /// for an unkeyed hash).
+    pub fn new(key: &[u8]) -> Blake2bHasher<N> {
+        Blake2bHasher { state: blake2b::Blake2b::with_key(N, key) }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.state.update(data);
+    }
+
+    pub fn finalize(self) -> Blake2b<N> {
+        let digest = self.state.finalize();
+        let bytes = into_bytes(digest.as_bytes())
+            .expect("blake2b digest has the requested length");
+        Blake2b { bytes: bytes }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<const N: usize> io::Write for Blake2bHasher<N> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// 32-byte blake2s hash of a byte string. Blake2s is built for 32-bit
+/// platforms, so it suits embedded targets better than `Blake2`.
+///
+/// ```
+/// use pender::event::Event;
+/// use pender::hash::Blake2s;
+///
+/// let root: Event<Blake2s> = Event::with_parent(b"potato", None);
+/// assert_eq!(root.hash().bytes.len(), 32);
+/// ```
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Blake2s {
+    pub bytes: [u8; 32]
+}
+
+impl Blake2s {
+    /// Unkeyed hash of `obj`.
+    pub fn new(obj: &[u8]) -> Blake2s {
+        Blake2s::new_keyed(b"", obj)
+    }
+
+    /// Keyed hash of `obj`. The key may be up to 32 bytes long.
+    pub fn new_keyed(key: &[u8], obj: &[u8]) -> Blake2s {
+        let digest = blake2s::blake2s(32, key, obj);
+        let bytes = into_bytes(digest.as_bytes())
+            .expect("blake2s digest has the requested length");
+        Blake2s { bytes: bytes }
+    }
+
+    /// Unkeyed hash of the concatenation of `parts`, computed without
+    /// building the concatenation.
+    pub fn new_concat(parts: &[&[u8]]) -> Blake2s {
+        Blake2s::new_concat_keyed(b"", parts)
+    }
+
+    /// Like `new_concat`, under `key` (see `new_keyed`).
+    pub fn new_concat_keyed(key: &[u8], parts: &[&[u8]]) -> Blake2s {
+        let mut state = blake2s::Blake2s::with_key(32, key);
+        for part in parts {
+            state.update(part);
+        }
+        let bytes = into_bytes(state.finalize().as_bytes())
+            .expect("blake2s digest has the requested length");
+        Blake2s { bytes: bytes }
+    }
+
+    /// Lowercase hex encoding of the hash (64 characters).
+    pub fn to_hex(&self) -> String {
+        to_hex(&self.bytes)
+    }
+
+    /// Parse a hash from its hex encoding (see `Blake2b::from_hex`).
+    pub fn from_hex(s: &str) -> Result<Blake2s, HashParseError> {
+        Ok(Blake2s { bytes: from_hex(s)? })
+    }
+}
+
+impl EventHash for Blake2s {
+    fn hash_parts_keyed(key: &[u8], parts: &[&[u8]]) -> Blake2s {
+        Blake2s::new_concat_keyed(key, parts)
+    }
+}
+
+impl Debug for Blake2s {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(f, "Blake2s {{ bytes: \"{}\" }}", self.to_hex().to_uppercase())
+    }
+}
+
+impl AsRef<[u8]> for Blake2s {
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+/// Serialized as a hex string (see `to_hex`).
+#[cfg(feature = "serde")]
+impl Serialize for Blake2s {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_hex())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Blake2s {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Blake2s, D::Error> {
+        let bytes = deserializer.deserialize_str(HexVisitor)?;
+        Ok(Blake2s { bytes: bytes })
+    }
+}
+
+/// Reasons a string could not be parsed by `Blake2b::from_hex`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum HashParseError {
+    /// The input was not two characters per hash byte long.
+    BadLength(usize),
+    /// The input contained a character that is not a hex digit.
+    InvalidChar(char),
+}
+
+impl<const N: usize> Copy for Blake2b<N> { }
+
+impl<const N: usize> Clone for Blake2b<N> {
+    fn clone(&self) -> Blake2b<N> {
         *self
     }
 }
 
-impl PartialEq for Blake2 {
-    fn eq(&self, other: &Blake2) -> bool {
+impl<const N: usize> PartialEq for Blake2b<N> {
+    fn eq(&self, other: &Blake2b<N>) -> bool {
         self.bytes.iter()
             .zip(other.bytes.iter())
             .all(|(x, y)| x == y)
     }
 }
 
-impl Eq for Blake2 { }
+impl<const N: usize> Eq for Blake2b<N> { }
+
+/// Lexicographic order of the bytes, consistent with `Eq`.
+impl<const N: usize> PartialOrd for Blake2b<N> {
+    fn partial_cmp(&self, other: &Blake2b<N>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
 
-impl Hash for Blake2 {
+impl<const N: usize> Ord for Blake2b<N> {
+    fn cmp(&self, other: &Blake2b<N>) -> Ordering {
+        self.bytes.cmp(&other.bytes)
+    }
+}
+
+impl<const N: usize> Hash for Blake2b<N> {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.bytes.hash(state);
     }
 }
 
-impl Debug for Blake2 {
+impl<const N: usize> Debug for Blake2b<N> {
     fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
         let mut hex = String::new();
         for &byte in self.bytes.iter() {
-            write!(&mut hex, "{:X}", byte).unwrap();
+            write!(&mut hex, "{:02X}", byte).unwrap();
         }
         write!(f, "Blake2 {{ bytes: \"{}\" }}", hex)
     }
 }
 
-fn into_64bytes(slice: &[u8]) -> [u8; 64] {
-    if slice.len() != 64 {
-        panic!("Slice is not 64 bytes")
+impl<const N: usize> AsRef<[u8]> for Blake2b<N> {
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+/// Copies the bytes of a hash computed elsewhere, e.g. received over FFI.
+///
+/// ```
+/// use std::convert::TryFrom;
+/// use pender::hash::{Blake2, HashError};
+///
+/// let hash = Blake2::new(b"potato");
+/// assert_eq!(Blake2::try_from(hash.as_ref()), Ok(hash));
+/// assert_eq!(Blake2::try_from(&b"potato"[..]), Err(HashError::BadLength(6)));
+/// ```
+impl<'a, const N: usize> TryFrom<&'a [u8]> for Blake2b<N> {
+    type Error = HashError;
+
+    fn try_from(slice: &'a [u8]) -> Result<Blake2b<N>, HashError> {
+        Ok(Blake2b { bytes: into_bytes(slice)? })
+    }
+}
+
+impl<const N: usize> TryFrom<Vec<u8>> for Blake2b<N> {
+    type Error = HashError;
+
+    fn try_from(vec: Vec<u8>) -> Result<Blake2b<N>, HashError> {
+        Blake2b::try_from(&vec[..])
+    }
+}
+
+/// Abbreviated to the first 8 hex characters and an ellipsis, like a git
+/// short hash. Use `Debug` or `to_hex` for the whole value.
+impl<const N: usize> Display for Blake2b<N> {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(f, "{}\u{2026}", self.short_hex(8))
+    }
+}
+
+/// Serialized as a hex string (see `to_hex`).
+#[cfg(feature = "serde")]
+impl<const N: usize> Serialize for Blake2b<N> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_hex())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, const N: usize> Deserialize<'de> for Blake2b<N> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Blake2b<N>, D::Error> {
+        let bytes = deserializer.deserialize_str(HexVisitor)?;
+        Ok(Blake2b { bytes: bytes })
+    }
+}
+
+#[cfg(feature = "serde")]
+struct HexVisitor<const N: usize>;
+
+#[cfg(feature = "serde")]
+impl<'de, const N: usize> Visitor<'de> for HexVisitor<N> {
+    type Value = [u8; N];
+
+    fn expecting(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(f, "a {}-character hex string", 2 * N)
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<[u8; N], E> {
+        from_hex(v).map_err(|err| match err {
+            HashParseError::BadLength(len) => E::invalid_length(len, &self),
+            HashParseError::InvalidChar(c) => E::invalid_value(Unexpected::Char(c), &self),
+        })
+    }
+}
+
+/// Lowercase hex encoding of `bytes`.
+fn to_hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(2 * bytes.len());
+    for &byte in bytes {
+        write!(&mut hex, "{:02x}", byte).unwrap();
     }
+    hex
+}
 
-    let mut array = [0u8; 64];
+/// Decode exactly `N` bytes from hex (either case).
+fn from_hex<const N: usize>(s: &str) -> Result<[u8; N], HashParseError> {
+    if s.len() != 2 * N {
+        return Err(HashParseError::BadLength(s.len()));
+    }
+
+    if let Some(c) = s.chars().find(|c| !c.is_ascii_hexdigit()) {
+        return Err(HashParseError::InvalidChar(c));
+    }
+
+    let mut bytes = [0u8; N];
+    for (byte, pair) in bytes.iter_mut().zip(s.as_bytes().chunks(2)) {
+        *byte = (hex_value(pair[0]) << 4) | hex_value(pair[1]);
+    }
+    Ok(bytes)
+}
+
+/// Value of an ASCII hex digit that has already been validated.
+fn hex_value(digit: u8) -> u8 {
+    (digit as char).to_digit(16).unwrap() as u8
+}
+
+/// Reasons a byte slice could not be converted into a hash.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum HashError {
+    /// The slice was not exactly as long as the hash; holds the slice length.
+    BadLength(usize),
+}
+
+fn into_bytes<const N: usize>(slice: &[u8]) -> Result<[u8; N], HashError> {
+    if slice.len() != N {
+        return Err(HashError::BadLength(slice.len()));
+    }
+
+    let mut array = [0u8; N];
     for (x, y) in slice.iter().zip(array.iter_mut()) {
         *y = *x;
     }
-    array
+    Ok(array)
 }
 
+/// A `Blake2s` that counts how many times it has been computed on the
+/// current thread, for tests checking that hashes are cached.
+#[cfg(test)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) struct CountingHash(Blake2s);
+
+#[cfg(test)]
+std::thread_local! {
+    static HASHES_COMPUTED: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+#[cfg(test)]
+impl CountingHash {
+    /// How many CountingHashes this thread has computed so far.
+    pub(crate) fn computed() -> usize {
+        HASHES_COMPUTED.with(|count| count.get())
+    }
+}
+
+#[cfg(test)]
+impl AsRef<[u8]> for CountingHash {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_ref()
+    }
+}
+
+#[cfg(test)]
+impl EventHash for CountingHash {
+    fn hash_parts_keyed(key: &[u8], parts: &[&[u8]]) -> CountingHash {
+        HASHES_COMPUTED.with(|count| count.set(count.get() + 1));
+        CountingHash(Blake2s::hash_parts_keyed(key, parts))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::convert::TryFrom;
+
+    use super::{into_bytes, Blake2, Blake2b, Blake2bHasher, Blake2Hasher, Blake2s, HashError,
+                HashParseError};
+
+    #[test]
+    fn equal_hashes_collide_as_hashmap_keys() {
+        let mut map = HashMap::new();
+        map.insert(Blake2::new(b"foo"), 1);
+        map.insert(Blake2::new(b"foo"), 2);
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.get(&Blake2::new(b"foo")), Some(&2));
+    }
+
+    #[test]
+    fn unequal_hashes_are_distinct_hashmap_keys() {
+        let mut map = HashMap::new();
+        map.insert(Blake2::new(b"foo"), 1);
+        map.insert(Blake2::new(b"bar"), 2);
+        assert_eq!(map.len(), 2);
+    }
+
+    #[test]
+    fn debug_pads_every_byte_to_two_hex_digits() {
+        let mut bytes = [0xABu8; 64];
+        bytes[0] = 0x00;
+        bytes[1] = 0x0A;
+        let debug = format!("{:?}", Blake2 { bytes: bytes });
+        assert_eq!(debug.len(), "Blake2 { bytes: \"\" }".len() + 128);
+        assert!(debug.starts_with("Blake2 { bytes: \"000AABAB"));
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        let hash = Blake2::new(b"foo");
+        let hex = hash.to_hex();
+        assert_eq!(hex.len(), 128);
+        assert_eq!(hex, hex.to_lowercase());
+        assert_eq!(Blake2::from_hex(&hex), Ok(hash));
+        assert_eq!(Blake2::from_hex(&hex.to_uppercase()), Ok(hash));
+    }
+
+    #[test]
+    fn from_hex_rejects_wrong_length() {
+        let hex = Blake2::new(b"foo").to_hex();
+        assert_eq!(Blake2::from_hex(""), Err(HashParseError::BadLength(0)));
+        assert_eq!(Blake2::from_hex(&hex[1..]), Err(HashParseError::BadLength(127)));
+        assert_eq!(Blake2::from_hex(&format!("{}0", hex)), Err(HashParseError::BadLength(129)));
+    }
+
+    #[test]
+    fn from_hex_rejects_non_hex_chars() {
+        let mut hex = Blake2::new(b"foo").to_hex();
+        hex.pop();
+        hex.push('g');
+        assert_eq!(Blake2::from_hex(&hex), Err(HashParseError::InvalidChar('g')));
+    }
+
+    #[test]
+    fn unkeyed_hash_matches_blake2b_test_vector() {
+        assert_eq!(Blake2::new(b"abc").to_hex(),
+                   "ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d1\
+                    7d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923");
+        assert_eq!(Blake2::new(b"abc"), Blake2::new_keyed(b"", b"abc"));
+    }
+
+    #[test]
+    fn different_keys_give_different_hashes() {
+        assert!(Blake2::new_keyed(b"key one", b"foo") !=
+                Blake2::new_keyed(b"key two", b"foo"));
+        assert!(Blake2::new_keyed(b"key one", b"foo") != Blake2::new(b"foo"));
+    }
+
+    #[test]
+    fn short_hash_round_trips_through_hex() {
+        let hash = Blake2b::<32>::new(b"foo");
+        assert_eq!(hash.bytes.len(), 32);
+        assert_eq!(hash.to_hex().len(), 64);
+        assert_eq!(Blake2b::<32>::from_hex(&hash.to_hex()), Ok(hash));
+    }
+
+    #[test]
+    fn short_hash_is_not_a_truncated_long_hash() {
+        let short = Blake2b::<32>::new(b"foo");
+        let long = Blake2::new(b"foo");
+        assert!(short.bytes[..] != long.bytes[..32]);
+    }
+
+    #[test]
+    fn from_hex_rejects_hex_of_the_wrong_width() {
+        let long = Blake2::new(b"foo").to_hex();
+        let short = Blake2b::<32>::new(b"foo").to_hex();
+        assert_eq!(Blake2b::<32>::from_hex(&long), Err(HashParseError::BadLength(128)));
+        assert_eq!(Blake2::from_hex(&short), Err(HashParseError::BadLength(64)));
+    }
+
+    #[test]
+    fn into_bytes_accepts_exact_length() {
+        assert_eq!(into_bytes::<4>(&[1, 2, 3, 4]), Ok([1, 2, 3, 4]));
+    }
+
+    #[test]
+    fn into_bytes_rejects_short_slice() {
+        assert_eq!(into_bytes::<64>(&[0u8; 63]), Err(HashError::BadLength(63)));
+        assert_eq!(into_bytes::<64>(&[]), Err(HashError::BadLength(0)));
+    }
+
+    #[test]
+    fn into_bytes_rejects_long_slice() {
+        assert_eq!(into_bytes::<32>(&[0u8; 64]), Err(HashError::BadLength(64)));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serializes_as_hex_string() {
+        let hash = Blake2::new(b"foo");
+        let json = ::serde_json::to_string(&hash).unwrap();
+        assert_eq!(json, format!("\"{}\"", hash.to_hex()));
+        assert_eq!(::serde_json::from_str::<Blake2>(&json).unwrap(), hash);
+        assert!(::serde_json::from_str::<Blake2>("\"abc\"").is_err());
+    }
+
+    #[test]
+    fn ct_eq_agrees_with_eq() {
+        let foo = Blake2::new(b"foo");
+        let mut last_byte_differs = foo;
+        last_byte_differs.bytes[63] ^= 1;
+
+        assert!(foo.ct_eq(&Blake2::new(b"foo")));
+        assert!(!foo.ct_eq(&Blake2::new(b"bar")));
+        assert!(!foo.ct_eq(&last_byte_differs));
+        assert_eq!(foo.ct_eq(&last_byte_differs), foo == last_byte_differs);
+    }
+
+    #[test]
+    fn xor_is_commutative_and_associative() {
+        let a = Blake2::new(b"a");
+        let b = Blake2::new(b"b");
+        let c = Blake2::new(b"c");
+        assert_eq!(a.xor(&b), b.xor(&a));
+        assert_eq!(a.xor(&b).xor(&c), a.xor(&b.xor(&c)));
+
+        let set = [a, b, c];
+        let folded = set.iter().fold(Blake2::zero(), |acc, h| acc.xor(h));
+        assert_eq!(folded, [c, a, b].iter().fold(Blake2::zero(), |acc, h| acc.xor(h)));
+    }
+
+    #[test]
+    fn xor_with_self_is_zero() {
+        let a = Blake2::new(b"a");
+        assert_eq!(a.xor(&a), Blake2::zero());
+        assert_eq!(a.xor(&Blake2::zero()), a);
+        assert_eq!(Blake2b::<32>::zero().as_ref(), &[0; 32][..]);
+    }
+
+    #[test]
+    fn display_shows_short_hash() {
+        let hash = Blake2::new(b"foo");
+        let display = format!("{}", hash);
+        assert_eq!(display.chars().count(), 9);
+        assert_eq!(display, format!("{}\u{2026}", &hash.to_hex()[..8]));
+    }
+
+    #[test]
+    fn short_hex_has_requested_length() {
+        let hash = Blake2::new(b"foo");
+        assert_eq!(hash.short_hex(16), &hash.to_hex()[..16]);
+        assert_eq!(hash.short_hex(0), "");
+        assert_eq!(hash.short_hex(1000), hash.to_hex());
+    }
+
+    #[test]
+    fn new_concat_equals_hash_of_concatenation() {
+        assert_eq!(Blake2::new_concat(&[b"foo", b"bar", b""]), Blake2::new(b"foobar"));
+        assert_eq!(Blake2::new_concat(&[]), Blake2::new(b""));
+        assert_eq!(Blake2b::<32>::new_concat(&[b"fo", b"o"]), Blake2b::<32>::new(b"foo"));
+    }
+
+    #[test]
+    fn try_from_accepts_exact_length() {
+        let hash = Blake2::new(b"foo");
+        assert_eq!(Blake2::try_from(&hash.bytes[..]), Ok(hash));
+        assert_eq!(Blake2::try_from(hash.bytes.to_vec()), Ok(hash));
+        assert_eq!(hash.as_ref(), &hash.bytes[..]);
+    }
+
+    #[test]
+    fn try_from_rejects_short_input() {
+        assert_eq!(Blake2::try_from(&[0u8; 63][..]), Err(HashError::BadLength(63)));
+        assert_eq!(Blake2::try_from(Vec::new()), Err(HashError::BadLength(0)));
+    }
+
+    #[test]
+    fn try_from_rejects_long_input() {
+        assert_eq!(Blake2::try_from(&[0u8; 65][..]), Err(HashError::BadLength(65)));
+        assert_eq!(Blake2b::<32>::try_from(vec![0u8; 64]), Err(HashError::BadLength(64)));
+    }
+
+    #[test]
+    fn sorts_in_byte_lexicographic_order() {
+        let mut hashes: Vec<Blake2> = [b"foo", b"bar", b"baz", b"qux"].iter()
+            .map(|fact| Blake2::new(&fact[..]))
+            .collect();
+        let mut by_bytes = hashes.clone();
+        by_bytes.sort_by(|a, b| a.bytes[..].cmp(&b.bytes[..]));
+
+        hashes.sort();
+        assert_eq!(hashes, by_bytes);
+        for pair in hashes.windows(2) {
+            assert!(pair[0] < pair[1]);
+        }
+        let mut resorted = hashes.clone();
+        resorted.reverse();
+        resorted.sort();
+        assert_eq!(resorted, hashes);
+    }
+
+    #[test]
+    fn hasher_fed_in_chunks_matches_one_shot_hash() {
+        let data: Vec<u8> = (0..1000u32).map(|i| (i % 251) as u8).collect();
+        let mut hasher = Blake2Hasher::new(b"");
+        hasher.update(&data[..10]);
+        hasher.update(&data[10..500]);
+        hasher.update(&data[500..]);
+        assert_eq!(hasher.finalize(), Blake2::new(&data));
+    }
+
+    #[test]
+    fn keyed_hasher_matches_new_keyed() {
+        let mut hasher = Blake2bHasher::<32>::new(b"key");
+        hasher.update(b"foo");
+        assert_eq!(hasher.finalize(), Blake2b::<32>::new_keyed(b"key", b"foo"));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn hasher_is_writable() {
+        use std::io::{self, Write};
+
+        let mut hasher = Blake2Hasher::new(b"");
+        io::copy(&mut &b"potato"[..], &mut hasher).unwrap();
+        hasher.flush().unwrap();
+        assert_eq!(hasher.finalize(), Blake2::new(b"potato"));
+    }
+
+    #[test]
+    fn blake2s_matches_test_vector() {
+        assert_eq!(Blake2s::new(b"abc").to_hex(),
+                   "508c5e8c327c14e2e1a72ba34eeb452f37458b209ed63a294d999b4c86675982");
+        assert_eq!(Blake2s::new_concat(&[b"a", b"bc"]), Blake2s::new(b"abc"));
+    }
+
+    #[test]
+    fn blake2s_hex_round_trip() {
+        let hash = Blake2s::new(b"foo");
+        assert_eq!(Blake2s::from_hex(&hash.to_hex()), Ok(hash));
+        assert_eq!(Blake2s::from_hex(&Blake2::new(b"foo").to_hex()),
+                   Err(HashParseError::BadLength(128)));
+    }
+}
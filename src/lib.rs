@@ -1,9 +1,57 @@
 #![feature(plugin)]
 #![plugin(clippy)]
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+//! Without the default `std` feature Pender builds as `no_std` on `alloc`:
+//! Fragments store their Events in a `BTreeMap` rather than a `HashMap`
+//! (see `collections`), timestamps are all 0, and the reading and writing
+//! methods, which need `std::io`, are left out, along with `archive` and
+//! `stream`, and so is `BloomFilter::false_positive_rate`. Building for a
+//! target with no `std` at all checks that nothing else slips in:
+//!
+//! ```text
+//! rustup target add thumbv7em-none-eabi
+//! cargo build --no-default-features --target thumbv7em-none-eabi
+//! cargo build --no-default-features --features serde --target thumbv7em-none-eabi
+//! ```
+//!
+//! The optional `mmap` feature adds `mmap`, for using a Fragment stored in
+//! a file without reading it into memory.
+
+#[cfg(not(feature = "std"))]
+#[macro_use]
+extern crate alloc;
+#[cfg(any(feature = "std", test))]
+extern crate core;
 
 extern crate blake2_rfc;
+#[cfg(feature = "mmap")]
+extern crate memmap2;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
 
+#[cfg(feature = "std")]
+pub mod archive;
+pub mod bloom;
+pub mod collections;
+pub mod error;
 pub mod event;
 pub mod fragment;
-mod hash;
-mod summary;
+pub mod hash;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+pub mod shared;
+#[cfg(feature = "std")]
+pub mod stream;
+pub mod summary;
+pub mod sync;
+
+/// The parts of the `std` prelude that Pender uses, for `no_std` builds.
+#[cfg(not(feature = "std"))]
+mod prelude {
+    pub use alloc::boxed::Box;
+    pub use alloc::string::{String, ToString};
+    pub use alloc::vec::Vec;
+}
@@ -0,0 +1,184 @@
+use std::fs::File;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use event::{self, DecodeError, Event, NODE_TAG, ROOT_TAG};
+use fragment::{Fragment, LoadError};
+use hash::Blake2;
+
+/// A Fragment file written by `Fragment::write_to`, mapped into memory
+/// (see `Fragment::from_mmap`).
+///
+/// The file is checked when it is mapped, but not copied: the Facts of the
+/// Fragment given by `as_fragment` borrow straight from the mapping, so
+/// they live as long as the MappedFragment does. Only the parents of Merge
+/// Events are copied out, since they must be laid out as hashes.
+///
+/// The mapping reflects the file, so the file must not be changed, by this
+/// process or any other, while it is mapped; hence mapping it is `unsafe`.
+///
+/// # Example
+/// ```
+/// use pender::fragment::Fragment;
+///
+/// let mut frag = Fragment::new();
+/// frag.append_all(&[b"one", b"two"]).unwrap();
+/// let path = std::env::temp_dir().join(format!("pender-doc-{}", std::process::id()));
+/// frag.write_to(&mut std::fs::File::create(&path).unwrap()).unwrap();
+///
+/// // Nothing else touches the file while it is mapped.
+/// let mapped = unsafe { Fragment::from_mmap(&path) }.unwrap();
+/// assert_eq!(mapped.as_fragment(), frag);
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct MappedFragment {
+    map: Mmap,
+    head: Option<Blake2>,
+    events: Vec<MappedEvent>,
+}
+
+/// Where an Event lies in the mapping.
+#[derive(Debug)]
+struct MappedEvent {
+    hash: Blake2,
+    tag: u8,
+    parents: Vec<Blake2>,
+    fact: Range<usize>,
+}
+
+impl MappedFragment {
+    /// Map the file at `path` and check that it holds a Fragment. Malformed
+    /// files fail with `io::ErrorKind::InvalidData`, wrapping a
+    /// `LoadError`.
+    ///
+    /// # Safety
+    /// The file must not be modified or truncated, by this process or any
+    /// other, for as long as the MappedFragment or any Fragment borrowed
+    /// from it lives. Otherwise the Facts it hands out could change or
+    /// vanish while borrowed, which is undefined behaviour.
+    pub unsafe fn open(path: &Path) -> io::Result<MappedFragment> {
+        let file = File::open(path)?;
+        let map = Mmap::map(&file)?;
+        MappedFragment::check(map).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    fn check(map: Mmap) -> Result<MappedFragment, LoadError> {
+        let (count, head, mut pos) = Fragment::read_header(&map)?;
+        let mut events = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let (tag, parents, fact) = event::decode_parts(&map[pos..])?;
+            let mut event = MappedEvent {
+                hash: Blake2::zero(),
+                tag: tag,
+                parents: parents,
+                fact: pos + fact.start..pos + fact.end,
+            };
+            pos = event.fact.end;
+            event.hash = event.borrow(&map).hash();
+            events.push(event);
+        }
+        if pos != map.len() {
+            return Err(LoadError::Decode(DecodeError::TrailingBytes(map.len() - pos)));
+        }
+
+        if let Some(head) = head {
+            if !events.iter().any(|event| event.hash == head) {
+                return Err(LoadError::MissingHead(head));
+            }
+        }
+        Ok(MappedFragment { map: map, head: head, events: events })
+    }
+
+    /// A Fragment whose Facts borrow from the mapping.
+    pub fn as_fragment(&self) -> Fragment<'_> {
+        let mut frag = Fragment::with_capacity(self.events.len());
+        for event in &self.events {
+            frag.events.insert(event.hash, event.borrow(&self.map));
+        }
+        frag.head = self.head.map(|head| frag.events[&head]);
+        frag
+    }
+}
+
+impl MappedEvent {
+    fn borrow<'a>(&'a self, map: &'a [u8]) -> Event<'a> {
+        let fact = &map[self.fact.clone()];
+        match self.tag {
+            ROOT_TAG => Event::Root { fact: fact },
+            NODE_TAG => Event::Node { fact: fact, parent_hash: self.parents[0] },
+            _ => Event::Merge { fact: fact, parents: &self.parents },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io;
+    use std::path::PathBuf;
+    use std::process;
+
+    use event::Event;
+    use fragment::Fragment;
+
+    /// A file in the temporary directory, removed when dropped.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> TempFile {
+            TempFile(env::temp_dir().join(format!("pender-{}-{}", name, process::id())))
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn write(file: &TempFile, frag: &Fragment) {
+        frag.write_to(&mut File::create(&file.0).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn mapped_facts_borrow_from_the_mapping() {
+        let file = TempFile::new("borrow");
+        let mut frag = Fragment::new();
+        frag.append_all(&[b"one", b"two", b"three"]).unwrap();
+        let head = frag.head.unwrap();
+        let side = Event::new(b"side", None);
+        frag.append_event(side).unwrap();
+        let parents = [head.hash(), side.hash()];
+        frag.append_event(Event::new_merge(b"merge", &parents)).unwrap();
+        write(&file, &frag);
+
+        let mapped = unsafe { Fragment::from_mmap(&file.0) }.unwrap();
+        let loaded = mapped.as_fragment();
+        assert_eq!(loaded, frag);
+
+        let map = mapped.map.as_ptr_range();
+        for event in loaded.iter() {
+            let fact = event.fact().as_ptr_range();
+            assert!(map.start <= fact.start && fact.end <= map.end);
+        }
+        let facts: Vec<&[u8]> = loaded.summarize("test").facts().collect();
+        assert_eq!(facts, vec![&b"merge"[..], &b"three"[..], &b"two"[..], &b"one"[..]]);
+    }
+
+    #[test]
+    fn malformed_file_is_invalid_data() {
+        let file = TempFile::new("malformed");
+        fs::write(&file.0, b"PNDR\x01").unwrap();
+        let malformed = unsafe { Fragment::from_mmap(&file.0) };
+        assert_eq!(malformed.unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        let missing = TempFile::new("missing");
+        let missing = unsafe { Fragment::from_mmap(&missing.0) };
+        assert_eq!(missing.unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,106 @@
+#[cfg(not(feature = "std"))]
+use alloc::sync::Arc;
+#[cfg(feature = "std")]
+use std::sync::Arc;
+
+use event::Event;
+use fragment::Fragment;
+use hash::{Blake2, EventHash};
+
+/// A Fragment that can be read from several threads at once.
+///
+/// Since the Fragment is shared for as long as any clone of the
+/// SharedFragment lives, which can be for the rest of the program, its
+/// Facts must be `'static`: literals, or buffers the application has leaked
+/// (e.g. with `Box::leak`). It can no longer be changed once shared.
+///
+/// # Example
+/// ```
+/// use std::thread;
+/// use pender::fragment::Fragment;
+/// use pender::shared::SharedFragment;
+///
+/// let mut frag = Fragment::new();
+/// frag.append_all(&[b"one", b"two"]).unwrap();
+/// let shared = SharedFragment::new(frag);
+///
+/// let walker = shared.clone();
+/// let count = thread::spawn(move || walker.walk().count()).join().unwrap();
+/// assert_eq!(count, 2);
+/// ```
+#[derive(Clone, Debug)]
+pub struct SharedFragment<H: EventHash + 'static = Blake2> {
+    fragment: Arc<Fragment<'static, H>>,
+}
+
+impl<H: EventHash + 'static> SharedFragment<H> {
+    pub fn new(fragment: Fragment<'static, H>) -> SharedFragment<H> {
+        SharedFragment { fragment: Arc::new(fragment) }
+    }
+
+    pub fn fragment(&self) -> &Fragment<'static, H> {
+        &self.fragment
+    }
+
+    /// Walks the chain from the head back to its root, like
+    /// `Fragment::summarize`. The walk holds its own reference to the
+    /// Fragment, so it can be sent to another thread.
+    pub fn walk(&self) -> Walk<H> {
+        Walk {
+            fragment: Arc::clone(&self.fragment),
+            next: self.fragment.head.map(|head| self.fragment.hash_of(head)),
+        }
+    }
+}
+
+/// Iterator returned by `SharedFragment::walk`. Stops at the root, or at
+/// the first missing Event.
+#[derive(Clone, Debug)]
+pub struct Walk<H: EventHash + 'static = Blake2> {
+    fragment: Arc<Fragment<'static, H>>,
+    next: Option<H>,
+}
+
+impl<H: EventHash + 'static> Iterator for Walk<H> {
+    type Item = Event<'static, H>;
+
+    fn next(&mut self) -> Option<Event<'static, H>> {
+        let event = self.next.and_then(|hash| self.fragment.get(&hash).cloned());
+        self.next = event.and_then(|event| event.parent());
+        event
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use fragment::Fragment;
+    use super::SharedFragment;
+
+    #[test]
+    fn threads_walk_the_same_chain() {
+        let mut frag = Fragment::new();
+        frag.append_all(&[b"one", b"two", b"three"]).unwrap();
+        let head = frag.head;
+        let shared = SharedFragment::new(frag);
+
+        let walkers: Vec<_> = (0..4)
+            .map(|_| {
+                let shared = shared.clone();
+                thread::spawn(move || {
+                    let walked: Vec<_> = shared.walk().collect();
+                    (walked.first().map(|event| event.hash()), walked.len())
+                })
+            })
+            .collect();
+        for walker in walkers {
+            assert_eq!(walker.join().unwrap(), (head.map(|head| head.hash()), 3));
+        }
+    }
+
+    #[test]
+    fn empty_fragment_walk_is_empty() {
+        assert_eq!(SharedFragment::new(Fragment::new()).walk().next(), None);
+    }
+}
@@ -0,0 +1,220 @@
+use std::io::{self, Read};
+
+use event::{self, DecodeError, OwnedEvent};
+use fragment::{LoadError, HEADER_LEN, MAGIC, VERSION};
+use hash::Blake2;
+
+/// Reads the Events of a Fragment written by `Fragment::write_to` one at a
+/// time, so that a Fragment too large to hold in memory can still be
+/// processed.
+///
+/// Each Event is decoded and checked as it is read; the first error ends
+/// the stream. Errors are `LoadError`s, since reading can fail as well as
+/// decoding. Once every declared Event has been read, the stream fails
+/// if there are bytes left over or none of the Events was the head.
+///
+/// # Example
+/// ```
+/// use pender::fragment::Fragment;
+/// use pender::stream::FragmentStreamReader;
+///
+/// let mut frag = Fragment::new();
+/// frag.append_all(&[b"one", b"two", b"three"]).unwrap();
+/// let mut buf = Vec::new();
+/// frag.write_to(&mut buf).unwrap();
+///
+/// let stream = FragmentStreamReader::new(&buf[..]).unwrap();
+/// assert_eq!(stream.head(), Some(frag.head.unwrap().hash()));
+/// let events: Result<Vec<_>, _> = stream.collect();
+/// assert_eq!(events.unwrap().len(), 3);
+/// ```
+#[derive(Debug)]
+pub struct FragmentStreamReader<R> {
+    reader: R,
+    head: Option<Blake2>,
+    remaining: u64,
+    head_seen: bool,
+    done: bool,
+}
+
+impl<R: Read> FragmentStreamReader<R> {
+    /// Read and check the header of the Fragment, leaving its Events to be
+    /// read by iterating.
+    pub fn new(mut reader: R) -> Result<FragmentStreamReader<R>, LoadError> {
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        read_exactly(&mut reader, &mut header, HEADER_LEN as u64)?;
+        if &header[..4] != MAGIC {
+            return Err(LoadError::BadMagic);
+        }
+        if header[4] != VERSION {
+            return Err(LoadError::UnsupportedVersion(header[4]));
+        }
+        let mut count = [0u8; 8];
+        count.copy_from_slice(&header[5..13]);
+
+        let head = match header[13] {
+            0 => None,
+            _ => {
+                let mut buf = Vec::with_capacity(64);
+                read_exactly(&mut reader, &mut buf, 64)?;
+                let mut bytes = [0u8; 64];
+                bytes.copy_from_slice(&buf);
+                Some(Blake2 { bytes: bytes })
+            },
+        };
+        Ok(FragmentStreamReader {
+            reader: reader,
+            head: head,
+            remaining: u64::from_be_bytes(count),
+            head_seen: head.is_none(),
+            done: false,
+        })
+    }
+
+    /// The hash of the Fragment's head, as given in the header.
+    pub fn head(&self) -> Option<Blake2> {
+        self.head
+    }
+
+    /// How many Events the header says are still to come.
+    pub fn remaining(&self) -> u64 {
+        self.remaining
+    }
+
+    fn next_event(&mut self) -> Result<Option<OwnedEvent>, LoadError> {
+        if self.remaining == 0 {
+            let trailing = io::copy(&mut self.reader, &mut io::sink())?;
+            if trailing != 0 {
+                return Err(LoadError::Decode(DecodeError::TrailingBytes(trailing as usize)));
+            }
+            if let (Some(head), false) = (self.head, self.head_seen) {
+                return Err(LoadError::MissingHead(head));
+            }
+            return Ok(None);
+        }
+
+        // Gather the encoded Event, reading each length before what it
+        // covers, then decode it as a whole.
+        let mut buf = Vec::new();
+        read_exactly(&mut self.reader, &mut buf, 1)?;
+        match buf[0] {
+            event::ROOT_TAG => (),
+            event::NODE_TAG => read_exactly(&mut self.reader, &mut buf, 64)?,
+            event::MERGE_TAG => {
+                let count = read_len(&mut self.reader, &mut buf)?;
+                let len = count.checked_mul(64).ok_or(DecodeError::Truncated)?;
+                read_exactly(&mut self.reader, &mut buf, len)?;
+            },
+            tag => return Err(LoadError::Decode(DecodeError::BadTag(tag))),
+        }
+        let len = read_len(&mut self.reader, &mut buf)?;
+        read_exactly(&mut self.reader, &mut buf, len)?;
+
+        let (event, _) = event::decode(&buf)?;
+        self.remaining -= 1;
+        if Some(event.hash()) == self.head {
+            self.head_seen = true;
+        }
+        Ok(Some(event))
+    }
+}
+
+impl<R: Read> Iterator for FragmentStreamReader<R> {
+    type Item = Result<OwnedEvent, LoadError>;
+
+    fn next(&mut self) -> Option<Result<OwnedEvent, LoadError>> {
+        if self.done {
+            return None;
+        }
+        let next = self.next_event().transpose();
+        if !matches!(next, Some(Ok(_))) {
+            self.done = true;
+        }
+        next
+    }
+}
+
+/// Append exactly `n` bytes from `r` to `buf`. Reading through `take` means
+/// a bogus length can't make this allocate more than the input holds.
+fn read_exactly<R: Read>(r: &mut R, buf: &mut Vec<u8>, n: u64) -> Result<(), LoadError> {
+    let read = r.take(n).read_to_end(buf)?;
+    if (read as u64) < n {
+        return Err(LoadError::Decode(DecodeError::Truncated));
+    }
+    Ok(())
+}
+
+/// Append a big-endian u64 from `r` to `buf`, returning its value.
+fn read_len<R: Read>(r: &mut R, buf: &mut Vec<u8>) -> Result<u64, LoadError> {
+    read_exactly(r, buf, 8)?;
+    let mut len = [0u8; 8];
+    len.copy_from_slice(&buf[buf.len() - 8..]);
+    Ok(u64::from_be_bytes(len))
+}
+
+#[cfg(test)]
+mod tests {
+    use event::DecodeError;
+    use fragment::{Fragment, LoadError};
+    use super::FragmentStreamReader;
+
+    fn written(facts: &[&'static [u8]]) -> Vec<u8> {
+        let mut frag = Fragment::new();
+        frag.append_all(facts).unwrap();
+        let mut buf = Vec::new();
+        frag.write_to(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn stream_yields_every_event_then_none() {
+        let buf = written(&[b"one", b"two", b"three"]);
+        let mut stream = FragmentStreamReader::new(&buf[..]).unwrap();
+        assert_eq!(stream.remaining(), 3);
+        for _ in 0..3 {
+            assert!(stream.next().unwrap().is_ok());
+        }
+        assert!(stream.next().is_none());
+        assert!(stream.next().is_none());
+    }
+
+    #[test]
+    fn stream_fails_on_corrupt_last_event() {
+        let mut buf = written(&[b"one", b"two", b"three"]);
+        buf.pop();
+        let results: Vec<_> = FragmentStreamReader::new(&buf[..]).unwrap().collect();
+        assert_eq!(results.len(), 3);
+        assert!(results[..2].iter().all(|result| result.is_ok()));
+        match results[2] {
+            Err(LoadError::Decode(DecodeError::Truncated)) => (),
+            ref other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn stream_fails_on_trailing_bytes() {
+        let mut buf = written(&[b"one"]);
+        buf.extend_from_slice(b"junk");
+        let mut stream = FragmentStreamReader::new(&buf[..]).unwrap();
+        assert!(stream.next().unwrap().is_ok());
+        match stream.next() {
+            Some(Err(LoadError::Decode(DecodeError::TrailingBytes(4)))) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(stream.next().is_none());
+    }
+
+    #[test]
+    fn stream_rejects_bad_header() {
+        match FragmentStreamReader::new(&b"NOPE\x01"[..]) {
+            Err(LoadError::Decode(DecodeError::Truncated)) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+        let mut buf = written(&[b"one"]);
+        buf[0] = b'X';
+        match FragmentStreamReader::new(&buf[..]) {
+            Err(LoadError::BadMagic) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
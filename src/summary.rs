@@ -1,10 +1,143 @@
-use hash::Blake2;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "serde")]
+use event::serde_fact;
+use fragment::Fragment;
+use hash::{Blake2, EventHash};
 
 /// A summary contains an arbitrary blob of data and the hash of the latest
 /// Event in the database which it summarizes.
+///
+/// # Example
+/// ```
+/// use pender::fragment::Fragment;
+/// use pender::summary::Summary;
+///
+/// let mut frag = Fragment::new();
+/// frag.append(b"one").unwrap();
+/// frag.append(b"two").unwrap();
+///
+/// let head = frag.head.unwrap().hash();
+/// let summary = Summary::new("count", b"2", head);
+/// assert_eq!(summary.name(), "count");
+/// assert_eq!(summary.summary(), b"2");
+/// assert_eq!(summary.event(), head);
+/// ```
+///
+/// A Summary of a Fragment whose Events use another `EventHash` names its
+/// Event by that hash too.
 #[derive(Copy, Clone, Debug, PartialEq)]
-struct Summary<'a> {
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Summary<'a, H: EventHash = Blake2> {
     name: &'a str,
+    #[cfg_attr(feature = "serde", serde(borrow, serialize_with = "serde_fact::serialize"))]
     summary: &'a [u8],
-    event: Blake2,
+    event: H,
+}
+
+impl<'a, H: EventHash> Summary<'a, H> {
+    pub fn new(name: &'a str, summary: &'a [u8], event: H) -> Summary<'a, H> {
+        Summary { name: name, summary: summary, event: event }
+    }
+
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// The summary data itself.
+    pub fn summary(&self) -> &'a [u8] {
+        self.summary
+    }
+
+    /// Hash of the latest Event summarized.
+    pub fn event(&self) -> H {
+        self.event
+    }
+
+    /// True if the summarized Event is present in `frag`.
+    pub fn is_valid_for(&self, frag: &Fragment<H>) -> bool {
+        frag.contains(&self.event)
+    }
+
+    /// True if the summarized Event is `frag`'s head or one of its
+    /// ancestors, through any parent of any Merge.
+    pub fn verify_reachable_from_head(&self, frag: &Fragment<H>) -> bool {
+        frag.head.is_some_and(|head| frag.is_ancestor_or_self(&self.event, &frag.hash_of(head)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use event::Event;
+    use fragment::Fragment;
+    use hash::Blake2s;
+    use super::Summary;
+
+    fn fragment<'a>() -> Fragment<'a> {
+        let mut frag = Fragment::new();
+        frag.append(b"one").unwrap();
+        frag.append(b"two").unwrap();
+        frag.append(b"three").unwrap();
+        frag
+    }
+
+    #[test]
+    fn summary_of_head_is_valid_and_reachable() {
+        let frag = fragment();
+        let summary = Summary::new("test", b"", frag.head.unwrap().hash());
+        assert!(summary.is_valid_for(&frag));
+        assert!(summary.verify_reachable_from_head(&frag));
+    }
+
+    #[test]
+    fn summary_of_ancestor_is_valid_and_reachable() {
+        let frag = fragment();
+        let summary = Summary::new("test", b"", Event::new(b"one", None).hash());
+        assert!(summary.is_valid_for(&frag));
+        assert!(summary.verify_reachable_from_head(&frag));
+    }
+
+    #[test]
+    fn summary_of_absent_event_is_invalid_and_unreachable() {
+        let frag = fragment();
+        let summary = Summary::new("test", b"", Event::new(b"four", frag.head).hash());
+        assert!(!summary.is_valid_for(&frag));
+        assert!(!summary.verify_reachable_from_head(&frag));
+    }
+
+    #[test]
+    fn summary_of_off_chain_event_is_valid_but_unreachable() {
+        let mut frag = fragment();
+        let head = frag.head;
+        let other = Event::new(b"other", None);
+        frag.append_event(other).unwrap();
+        frag.head = head;
+
+        let summary = Summary::new("test", b"", other.hash());
+        assert!(summary.is_valid_for(&frag));
+        assert!(!summary.verify_reachable_from_head(&frag));
+    }
+
+    #[test]
+    fn summary_reachable_through_second_merge_parent() {
+        let mut frag = fragment();
+        let head = frag.head.unwrap();
+        let side = Event::new(b"side", None);
+        frag.append_event(side).unwrap();
+        let parents = [head.hash(), side.hash()];
+        frag.append_event(Event::new_merge(b"merge", &parents)).unwrap();
+
+        let summary = Summary::new("test", b"", side.hash());
+        assert!(summary.verify_reachable_from_head(&frag));
+    }
+
+    #[test]
+    fn blake2s_summary_names_blake2s_event() {
+        let mut frag: Fragment<Blake2s> = Fragment::default();
+        let head = frag.append(b"one").unwrap();
+        let summary = Summary::new("count", b"1", head);
+        assert!(summary.is_valid_for(&frag));
+        assert!(summary.verify_reachable_from_head(&frag));
+    }
 }
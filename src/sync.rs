@@ -0,0 +1,148 @@
+use collections::Set;
+
+use event::Event;
+use fragment::{AppendError, Fragment};
+use hash::{Blake2, EventHash};
+#[cfg(not(feature = "std"))]
+use prelude::*;
+
+/// The hashes of Events a node is missing, to send to a peer, who answers
+/// with `Fragment::collect_events`.
+///
+/// Each answer can name further missing parents, so a node keeps asking
+/// until its WantList is empty (or the peer has nothing more to give), then
+/// passes everything it was sent to `receive`.
+///
+/// # Example
+/// ```
+/// use pender::fragment::Fragment;
+/// use pender::sync::{self, WantList};
+///
+/// let mut theirs = Fragment::new();
+/// theirs.append_all(&[b"one", b"two", b"three"]).unwrap();
+///
+/// // We only have their head.
+/// let mut ours = Fragment::new();
+/// ours.append_event(theirs.head.unwrap()).unwrap();
+///
+/// let mut received = Vec::new();
+/// loop {
+///     let wants = WantList::new(&ours, &received);
+///     let answer = theirs.collect_events(wants.hashes());
+///     if answer.is_empty() {
+///         break;
+///     }
+///     received.extend(answer);
+/// }
+/// sync::receive(&mut ours, &received).unwrap();
+/// assert_eq!(ours.verify(), Ok(()));
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct WantList<H: EventHash = Blake2> {
+    hashes: Vec<H>,
+}
+
+impl<H: EventHash> WantList<H> {
+    /// The parents named by Events in `fragment` or in `received` that are
+    /// stored in neither, sorted.
+    pub fn new(fragment: &Fragment<H>, received: &[Event<H>]) -> WantList<H> {
+        let have: Set<H> = received.iter().map(|&event| fragment.hash_of(event)).collect();
+        let orphans = fragment.orphans();
+        let named = orphans.iter()
+            .flat_map(|orphan| fragment.events[orphan].parents())
+            .chain(received.iter().flat_map(|event| event.parents()));
+
+        let mut hashes: Vec<H> = named
+            .filter(|parent| !fragment.contains(parent) && !have.contains(parent))
+            .cloned()
+            .collect();
+        hashes.sort();
+        hashes.dedup();
+        WantList { hashes: hashes }
+    }
+
+    pub fn hashes(&self) -> &[H] {
+        &self.hashes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+}
+
+impl<H: EventHash> Default for WantList<H> {
+    fn default() -> WantList<H> {
+        WantList { hashes: Vec::new() }
+    }
+}
+
+/// Add Events sent by a peer to `fragment` with `Fragment::try_append_event`,
+/// parents first, stopping at the first one that is refused.
+///
+/// Received Events are history being filled in, so the head is left where
+/// it was; use `Fragment::merge` to adopt a peer's head.
+pub fn receive<'a, H: EventHash>(fragment: &mut Fragment<'a, H>, received: &[Event<'a, H>])
+    -> Result<(), AppendError<H>>
+{
+    let mut batch = fragment.empty_like();
+    for &event in received {
+        batch.append_event(event)?;
+    }
+    let order = batch.topo_sort()
+        .expect("Events keyed by their own hashes can't form a cycle");
+
+    let head = fragment.head;
+    let result = order.iter()
+        .try_for_each(|hash| fragment.try_append_event(batch.events[hash]));
+    fragment.head = head;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use event::Event;
+    use fragment::{AppendError, Fragment};
+    use super::{receive, WantList};
+
+    #[test]
+    fn want_list_of_complete_fragment_is_empty() {
+        let mut frag = Fragment::new();
+        frag.append_all(&[b"1", b"2"]).unwrap();
+        assert!(WantList::new(&frag, &[]).is_empty());
+    }
+
+    #[test]
+    fn want_list_names_missing_parents() {
+        let root = Event::new(b"1", None);
+        let node = Event::new(b"2", Some(root));
+        let head = Event::new(b"3", Some(node));
+        let mut frag = Fragment::new();
+        frag.append_event(head).unwrap();
+
+        assert_eq!(WantList::new(&frag, &[]).hashes(), &[node.hash()]);
+        assert_eq!(WantList::new(&frag, &[node]).hashes(), &[root.hash()]);
+        assert!(WantList::new(&frag, &[node, root]).is_empty());
+    }
+
+    #[test]
+    fn receive_appends_parents_first_and_keeps_head() {
+        let root = Event::new(b"1", None);
+        let node = Event::new(b"2", Some(root));
+        let head = Event::new(b"3", Some(node));
+        let mut frag = Fragment::new();
+        frag.append_event(head).unwrap();
+
+        assert_eq!(receive(&mut frag, &[node, root]), Ok(()));
+        assert_eq!(frag.head, Some(head));
+        assert_eq!(frag.verify(), Ok(()));
+    }
+
+    #[test]
+    fn receive_refuses_events_with_missing_parents() {
+        let root = Event::new(b"1", None);
+        let node = Event::new(b"2", Some(root));
+        let mut frag = Fragment::new();
+        assert_eq!(receive(&mut frag, &[node]), Err(AppendError::MissingParent(root.hash())));
+        assert!(frag.is_empty());
+    }
+}
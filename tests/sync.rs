@@ -0,0 +1,43 @@
+extern crate pender;
+
+use pender::event::Event;
+use pender::fragment::Fragment;
+use pender::sync::{self, WantList};
+
+/// Node A holds only the newest part of node B's history and asks B for
+/// everything it's missing.
+#[test]
+fn node_fetches_missing_history_from_peer() {
+    let mut b = Fragment::new();
+    b.append_all(&[b"one", b"two", b"three", b"four"]).unwrap();
+    let b_head = b.head.unwrap();
+    let side = Event::new(b"side", None);
+    b.append_event(side).unwrap();
+    let parents = [b_head.hash(), side.hash()];
+    b.append_event(Event::new_merge(b"merge", &parents)).unwrap();
+    let merge = b.head.unwrap();
+
+    let mut a = Fragment::new();
+    a.append_event(merge).unwrap();
+    assert!(a.verify().is_err());
+
+    let mut received = Vec::new();
+    let mut rounds = 0;
+    loop {
+        let wants = WantList::new(&a, &received);
+        if wants.is_empty() {
+            break;
+        }
+        let answer = b.collect_events(wants.hashes());
+        assert_eq!(answer.len(), wants.hashes().len());
+        received.extend(answer);
+        rounds += 1;
+    }
+    assert_eq!(rounds, 4);
+
+    sync::receive(&mut a, &received).unwrap();
+    assert_eq!(a.verify(), Ok(()));
+    assert_eq!(a.head, Some(merge));
+    assert!(a.diff(&b).only_other.is_empty());
+    assert_eq!(a.len(), b.len());
+}